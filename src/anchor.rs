@@ -0,0 +1,230 @@
+use crate::ast::{TurboText, TurboTree};
+use crate::span::Span;
+use crate::visit::{walk_tree, walk_tree_mut, Visitor, VisitorMut};
+use crate::Diagnostic;
+use std::collections::HashSet;
+
+/// The text content without any formatting.
+pub(crate) fn plain_text(text: &TurboText) -> String {
+    match text {
+        TurboText::TextContainer { text, .. } | TurboText::Mod { text, .. } => {
+            text.iter().map(plain_text).collect()
+        }
+        TurboText::Link { alias, address, .. } => alias.as_ref().unwrap_or(address).clone(),
+        TurboText::Image { image, .. } => image.alt.clone().unwrap_or_default(),
+        TurboText::Plain { text, .. } | TurboText::Math { math: text, .. } => text.clone(),
+        TurboText::FigureRef {
+            number: Some((kind, number)),
+            ..
+        } => format!("{} {number}", kind.name()),
+        TurboText::FigureRef { id, .. } => format!("@{id}"),
+        TurboText::FootnoteRef { .. } | TurboText::Html { .. } => String::new(),
+        TurboText::NewLine { .. } => " ".to_string(),
+    }
+}
+
+/// Anchor id of a heading: lowercase words joined by `-`, words are made of
+/// any alphabetic or numeric characters.
+pub(crate) fn slug(text: &TurboText) -> String {
+    plain_text(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Removes a trailing `{#id}` from the text of a heading and returns the id.
+pub(crate) fn take_explicit_id(text: &mut TurboText) -> Option<String> {
+    let nodes = text.get_vec_mut()?;
+    // the parser splits plain text at `{` and `#`
+    let plain = nodes
+        .iter()
+        .rev()
+        .take_while(|node| matches!(node, TurboText::Plain { .. }))
+        .count();
+    let tail = nodes[nodes.len() - plain..]
+        .iter()
+        .map(plain_text)
+        .collect::<String>();
+    let trimmed = tail.trim_end().strip_suffix('}')?;
+    let start = trimmed.rfind("{#")?;
+    let id = &trimmed[start + 2..];
+    if id.is_empty() || id.contains(|c: char| c.is_whitespace() || c == '{' || c == '}') {
+        return None;
+    }
+    let id = id.to_string();
+
+    let mut remove = tail.len() - trimmed[..start].trim_end().len();
+    while remove > 0 {
+        let Some(TurboText::Plain { text, .. }) = nodes.last_mut() else {
+            break;
+        };
+        if text.len() <= remove {
+            remove -= text.len();
+            nodes.pop();
+        } else {
+            text.truncate(text.len() - remove);
+            remove = 0;
+        }
+    }
+    Some(id)
+}
+
+/// Gives every heading an id unique in the document. Explicit ids are kept,
+/// the others are the slug of the text, with `-1`, `-2`, ... appended in
+/// document order while the id is taken. A repeated explicit id is reported
+/// and made unique the same way.
+pub(crate) fn assign_ids(content: &mut [TurboTree], diagnostics: &mut Vec<Diagnostic>) {
+    let mut explicit = Explicit::default();
+    content.iter().for_each(|node| explicit.visit_tree(node));
+    diagnostics.extend(
+        explicit
+            .duplicates
+            .iter()
+            .map(|&heading| Diagnostic::DuplicateHeadingId {
+                id: explicit.ids[heading].clone().unwrap(),
+                file: None,
+                span: explicit.spans[heading],
+            }),
+    );
+    let mut assign = Assign {
+        taken: explicit.taken,
+        duplicates: explicit.duplicates,
+        heading: 0,
+    };
+    content
+        .iter_mut()
+        .for_each(|node| assign.visit_tree_mut(node));
+}
+
+/// Collects the ids of the headings in document order.
+pub(crate) fn heading_anchors(content: &[TurboTree]) -> HashSet<String> {
+    let mut explicit = Explicit::default();
+    content.iter().for_each(|node| explicit.visit_tree(node));
+    explicit.ids.into_iter().flatten().collect()
+}
+
+/// Explicit ids of the headings.
+#[derive(Default)]
+struct Explicit {
+    /// per heading in document order, `None` if it has no id yet
+    ids: Vec<Option<String>>,
+    /// per heading in document order
+    spans: Vec<Span>,
+    taken: HashSet<String>,
+    /// headings that repeat an explicit id
+    duplicates: Vec<usize>,
+}
+
+impl Visitor for Explicit {
+    fn visit_tree(&mut self, node: &TurboTree) {
+        if let TurboTree::Heading { id, span, .. } = node {
+            self.spans.push(*span);
+            if id.is_empty() {
+                self.ids.push(None);
+            } else {
+                if !self.taken.insert(id.clone()) {
+                    self.duplicates.push(self.ids.len());
+                }
+                self.ids.push(Some(id.clone()));
+            }
+        }
+        walk_tree(self, node)
+    }
+
+    // headings don't contain other headings
+    fn visit_text(&mut self, _text: &TurboText) {}
+}
+
+struct Assign {
+    taken: HashSet<String>,
+    duplicates: Vec<usize>,
+    /// index of the next heading in document order
+    heading: usize,
+}
+
+impl VisitorMut for Assign {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        if let TurboTree::Heading { id, text, .. } = node {
+            if id.is_empty() || self.duplicates.contains(&self.heading) {
+                let base = if id.is_empty() {
+                    slug(text)
+                } else {
+                    id.clone()
+                };
+                *id = self.unique(base);
+            }
+            self.heading += 1;
+        }
+        walk_tree_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, _text: &mut TurboText) {}
+}
+
+impl Assign {
+    fn unique(&mut self, base: String) -> String {
+        let base = if base.is_empty() {
+            "section".to_string()
+        } else {
+            base
+        };
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.taken.contains(&id) {
+            id = format!("{base}-{n}");
+            n += 1;
+        }
+        self.taken.insert(id.clone());
+        id
+    }
+}
+
+#[cfg(test)]
+mod anchor_tests {
+    use crate::{parse_string, HtmlRenderer, Renderer, TurboTree};
+
+    #[test]
+    fn unique_ids() {
+        let input =
+            "# Install *it* {#install}\n# Straße – 2024!\n# Install\n# A {#install}\n# !!\n";
+        let (parse, _) = parse_string(input).unwrap();
+        let (tree, diagnostics) = TurboTree::generate_with_diagnostics(parse).unwrap();
+        let (file, span) = diagnostics[0].location().unwrap();
+        assert_eq!(
+            (diagnostics.len(), diagnostics[0].to_string(), file),
+            (
+                1,
+                "heading id `install` is used more than once".to_string(),
+                None
+            )
+        );
+        assert_eq!(&input[span.range()], "# A {#install}\n");
+        let ids = tree
+            .get_vec()
+            .unwrap()
+            .iter()
+            .map(|node| match node {
+                TurboTree::Heading { id, .. } => id.as_str(),
+                _ => panic!("expected a heading"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            [
+                "install",
+                "straße-2024",
+                "install-1",
+                "install-2",
+                "section"
+            ]
+        );
+
+        let mut renderer = HtmlRenderer::new(None).with_permalinks(true);
+        renderer.render(&tree).unwrap();
+        assert!(renderer.finish().starts_with(
+            "<h1 id=\"install\">Install <b>it</b> <a class=\"permalink\" href=\"#install\">&#182;</a></h1>\n"
+        ));
+    }
+}
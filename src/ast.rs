@@ -1,89 +1,335 @@
-use crate::parser::{Turbo, TurboInlineRaw, TurboTextRaw};
-use crate::{Code, ListKind, TurboTextMod};
-use std::collections::HashSet;
+use crate::anchor::{self, plain_text, take_explicit_id};
+use crate::include::Resolver;
+use crate::parser::{TableCellRaw, Turbo, TurboInlineRaw, TurboTextRaw};
+use crate::safe::is_safe_address;
+use crate::span::Span;
+use crate::{
+    figure, footnote, numbering, toc, Alignment, Code, CodeHandlers, Diagnostic, Error, FigureKind,
+    Image, Lang, ListKind, ParseError, ParseErrorKind, SafeMode, SectionNumbering, StructureError,
+    TocEntry, TocOptions, TurboTextMod,
+};
 use std::fmt;
+use std::path::Path;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TurboTree {
     Root {
         content: Vec<TurboTree>,
+        span: Span,
+    },
+    Text {
+        text: TurboText,
+        span: Span,
     },
-    Text(TurboText),
     Heading {
         size: usize,
         text: TurboText,
+        /// Anchor id, unique in the document. Set with a trailing `{#id}`,
+        /// the slug of the text otherwise.
+        id: String,
+        /// `false` for `#* ` headings, which are never numbered.
+        numbered: bool,
+        /// The section number, set by [`Options::numbering`].
+        number: Option<String>,
+        span: Span,
     },
     List {
         kind: ListKind,
+        start: isize,
         items: Vec<TurboTree>,
+        span: Span,
     },
     ListItem {
         id: usize,
+        marker: Option<String>,
         check: Option<bool>,
         label: Box<Option<TurboTree>>,
         items: Vec<TurboTree>,
+        span: Span,
+    },
+    Code {
+        code: Code,
+        span: Span,
+    },
+    Quote {
+        content: Vec<TurboTree>,
+        attribution: Option<TurboText>,
+        span: Span,
+    },
+    Table {
+        header: Vec<Vec<TableCell>>,
+        alignments: Vec<Alignment>,
+        rows: Vec<Vec<TableCell>>,
+        span: Span,
+    },
+    FootnoteDef {
+        name: String,
+        text: TurboText,
+        span: Span,
+    },
+    Footnotes {
+        footnotes: Vec<Footnote>,
+        span: Span,
+    },
+    Horizontal {
+        span: Span,
+    },
+    Empty {
+        span: Span,
+    },
+    Include {
+        path: String,
+        span: Span,
+    },
+    /// A table of contents, `@[toc]` in the source. The entries are filled
+    /// in once the whole document is generated.
+    Toc {
+        entries: Vec<TocEntry>,
+        span: Span,
+    },
+    /// An image, table or code block followed by a caption line
+    /// `: <caption> {#label}`.
+    Figure {
+        kind: FigureKind,
+        /// Label references point to, set with a trailing `{#label}`.
+        id: Option<String>,
+        /// Numbered per kind once the whole document is generated.
+        number: Option<usize>,
+        /// `None` for a caption line with only a label.
+        caption: Option<TurboText>,
+        content: Vec<TurboTree>,
+        span: Span,
     },
-    Code(Code),
-    Horizontal,
-    Empty,
-    Include(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TurboText {
-    TextContainer(Vec<TurboText>),
+    TextContainer {
+        text: Vec<TurboText>,
+        span: Span,
+    },
     Mod {
         kind: TurboTextMod,
         text: Vec<TurboText>,
+        span: Span,
     },
     Link {
         alias: Option<String>,
         address: String,
+        span: Span,
+    },
+    Image {
+        image: Image,
+        span: Span,
+    },
+    FootnoteRef {
+        name: String,
+        number: Option<usize>,
+        occurrence: usize,
+        span: Span,
+    },
+    /// `@fig:arch`, the number is set once the whole document is generated
+    /// and stays `None` for labels no figure has.
+    FigureRef {
+        id: String,
+        number: Option<(FigureKind, usize)>,
+        span: Span,
+    },
+    Plain {
+        text: String,
+        span: Span,
+    },
+    Math {
+        math: String,
+        span: Span,
+    },
+    /// Raw HTML, other formats leave it out.
+    Html {
+        html: String,
+        span: Span,
+    },
+    NewLine {
+        span: Span,
     },
-    Plain(String),
-    NewLine,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableCell {
+    pub text: TurboText,
+    pub colspan: usize,
+    pub rowspan: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    pub name: String,
+    pub number: usize,
+    pub text: TurboText,
+    pub references: usize,
+    /// span of the definition
+    pub span: Span,
 }
 
 impl TurboTree {
     fn is_list_item(&self) -> bool {
+        matches!(self, TurboTree::ListItem { .. })
+    }
+
+    /// The source lines the node was generated from. Included content keeps
+    /// the spans of its own file.
+    pub fn span(&self) -> Span {
         match self {
-            TurboTree::ListItem { .. } => true,
-            _ => false,
+            TurboTree::Root { span, .. }
+            | TurboTree::Text { span, .. }
+            | TurboTree::Heading { span, .. }
+            | TurboTree::List { span, .. }
+            | TurboTree::ListItem { span, .. }
+            | TurboTree::Code { span, .. }
+            | TurboTree::Quote { span, .. }
+            | TurboTree::Table { span, .. }
+            | TurboTree::FootnoteDef { span, .. }
+            | TurboTree::Footnotes { span, .. }
+            | TurboTree::Horizontal { span }
+            | TurboTree::Empty { span }
+            | TurboTree::Include { span, .. }
+            | TurboTree::Toc { span, .. }
+            | TurboTree::Figure { span, .. } => *span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            TurboTree::Root { span, .. }
+            | TurboTree::Text { span, .. }
+            | TurboTree::Heading { span, .. }
+            | TurboTree::List { span, .. }
+            | TurboTree::ListItem { span, .. }
+            | TurboTree::Code { span, .. }
+            | TurboTree::Quote { span, .. }
+            | TurboTree::Table { span, .. }
+            | TurboTree::FootnoteDef { span, .. }
+            | TurboTree::Footnotes { span, .. }
+            | TurboTree::Horizontal { span }
+            | TurboTree::Empty { span }
+            | TurboTree::Include { span, .. }
+            | TurboTree::Toc { span, .. }
+            | TurboTree::Figure { span, .. } => span,
         }
     }
 }
 
 impl TurboText {
-    pub fn get_vec_mut(&mut self) -> &mut Vec<TurboText> {
+    /// The children of a container or modifier, `None` for any other node.
+    pub fn get_vec_mut(&mut self) -> Option<&mut Vec<TurboText>> {
+        match self {
+            TurboText::TextContainer { text, .. } => Some(text),
+            TurboText::Mod { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Appends `child` to a container or modifier, other nodes have no children.
+    fn push(&mut self, child: TurboText) {
+        if let Some(text) = self.get_vec_mut() {
+            text.push(child);
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            TurboText::TextContainer { span, .. }
+            | TurboText::Mod { span, .. }
+            | TurboText::Link { span, .. }
+            | TurboText::Image { span, .. }
+            | TurboText::FootnoteRef { span, .. }
+            | TurboText::FigureRef { span, .. }
+            | TurboText::Plain { span, .. }
+            | TurboText::Math { span, .. }
+            | TurboText::Html { span, .. }
+            | TurboText::NewLine { span } => *span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
         match self {
-            TurboText::TextContainer(vec) => vec,
-            TurboText::Mod { text, .. } => text,
-            _ => panic!("illegal call"),
+            TurboText::TextContainer { span, .. }
+            | TurboText::Mod { span, .. }
+            | TurboText::Link { span, .. }
+            | TurboText::Image { span, .. }
+            | TurboText::FootnoteRef { span, .. }
+            | TurboText::FigureRef { span, .. }
+            | TurboText::Plain { span, .. }
+            | TurboText::Math { span, .. }
+            | TurboText::Html { span, .. }
+            | TurboText::NewLine { span } => span,
         }
     }
 }
 
 impl TurboTree {
-    pub fn generate(parse: Turbo) -> Self {
-        let mut content = vec![];
-        let root = parse.root();
-        let mut idx = 0;
-        while idx < root.len() {
-            let (next_idx, next) = generate_recursive(root, idx, 0, None, &mut IdSerial { id: 0 });
-            content.push(next.unwrap());
-            idx = next_idx;
-        }
+    pub fn generate(parse: Turbo) -> Result<Self, Error> {
+        Ok(Self::generate_with_diagnostics(parse)?.0)
+    }
+
+    pub fn generate_with_diagnostics(parse: Turbo) -> Result<(Self, Vec<Diagnostic>), Error> {
+        Self::generate_with_options(parse, &Options::default())
+    }
+
+    pub fn generate_with_options(
+        parse: Turbo,
+        options: &Options,
+    ) -> Result<(Self, Vec<Diagnostic>), Error> {
+        let root = parse.root().ok_or(StructureError::NotRoot)?;
+        let mut context = Context::new(options);
+        let content = generate_content(root, &mut context)?;
+        let diagnostics = context.take_errors(None)?;
+        Ok(Self::finish(content, parse.span(), diagnostics, options))
+    }
+
+    /// Parses and generates the file at `path`, with all `@[...]` includes resolved
+    /// relative to the including file and links to other `.tmd` documents checked.
+    pub fn generate_file(path: impl AsRef<Path>) -> Result<(Self, Vec<Diagnostic>), Error> {
+        Self::generate_file_with_options(path, &Options::default())
+    }
 
-        let tree = TurboTree::Root { content };
-        tree
+    pub fn generate_file_with_options(
+        path: impl AsRef<Path>,
+        options: &Options,
+    ) -> Result<(Self, Vec<Diagnostic>), Error> {
+        let mut resolver = Resolver::new(true, options);
+        let (content, span) = resolver.generate_file(path.as_ref(), None, Path::new(""))?;
+        let (tree, mut diagnostics) = Self::finish(content, span, resolver.diagnostics, options);
+        diagnostics
+            .iter_mut()
+            .for_each(|diagnostic| diagnostic.locate(&resolver.files));
+        Ok((tree, diagnostics))
     }
 
-    pub fn get_vec(&self) -> &Vec<Self> {
+    fn finish(
+        mut content: Vec<TurboTree>,
+        span: Span,
+        mut diagnostics: Vec<Diagnostic>,
+        options: &Options,
+    ) -> (Self, Vec<Diagnostic>) {
+        options.code_handlers.apply(&mut content);
+        anchor::assign_ids(&mut content, &mut diagnostics);
+        if let Some(numbering) = &options.numbering {
+            numbering::number(&mut content, numbering);
+        }
+        figure::resolve(&mut content, &mut diagnostics);
+        footnote::resolve(&mut content, &mut diagnostics);
+        // after the footnotes, references in headings are counted once
+        toc::insert(&mut content, &options.toc);
+        (TurboTree::Root { content, span }, diagnostics)
+    }
+
+    /// The children of a root, list or list item, `None` for any other node.
+    pub fn get_vec(&self) -> Option<&Vec<Self>> {
         match self {
-            TurboTree::Root { content, .. } => content,
-            TurboTree::List { items, .. } => items,
-            TurboTree::ListItem { items, .. } => items,
-            _ => panic!("Illegal Call"),
+            TurboTree::Root { content, .. } => Some(content),
+            TurboTree::List { items, .. } => Some(items),
+            TurboTree::ListItem { items, .. } => Some(items),
+            _ => None,
         }
     }
 }
@@ -92,10 +338,175 @@ impl TurboTree {
 pub struct ListSetting {
     pub kind: ListKind,
     pub nesting_counter: usize,
+    /// number of the previous item
+    pub number: isize,
 }
 
-struct IdSerial {
+impl ListSetting {
+    /// The kind and number of the next item. `i`, `v` and `x` are parsed as
+    /// roman numerals, but continue an alphabetic list if they are the next letter.
+    fn next(&self, kind: &ListKind, number: isize) -> (ListKind, isize) {
+        let letter = match number {
+            1 => Some(9),
+            5 => Some(22),
+            10 => Some(24),
+            _ => None,
+        };
+        let alphabetic = matches!(
+            (&self.kind, kind),
+            (ListKind::AlphabetLower, ListKind::RomanLower)
+                | (ListKind::AlphabetUpper, ListKind::RomanUpper)
+        );
+        match letter {
+            Some(letter) if alphabetic && letter == self.number + 1 => (self.kind.clone(), letter),
+            _ => (kind.clone(), number),
+        }
+    }
+}
+
+/// Settings for parsing and generating the tree.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub modifiers: ModifierPolicy,
+    /// Restrictions for untrusted input, `None` trusts it.
+    pub safe: Option<SafeMode>,
+    /// Numbers headings if set.
+    pub numbering: Option<SectionNumbering>,
+    /// Headings listed by `@[toc]`.
+    pub toc: TocOptions,
+    /// Handlers for code blocks of unknown languages. They are trusted, their
+    /// markup is kept in the safe mode too.
+    pub code_handlers: CodeHandlers,
+}
+
+impl Options {
+    /// The default options with the default [`SafeMode`].
+    pub fn safe() -> Self {
+        Self {
+            safe: Some(SafeMode::default()),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn max_depth(&self) -> usize {
+        self.safe.as_ref().map_or(usize::MAX, |safe| safe.max_depth)
+    }
+}
+
+/// What happens to modifier flags that are never closed, or that are still
+/// open when a flag opened before them closes, like the `_` in `*a _b* c_`.
+/// They are reported either way.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ModifierPolicy {
+    /// The flag is kept as text.
+    Literal,
+    /// The modifier is closed at the end of the line, or before the flag that
+    /// closes around it.
+    #[default]
+    AutoClose,
+    /// Generation fails with [`Error::Parse`].
+    Error,
+}
+
+/// State shared while generating the content of a document and its includes.
+pub(crate) struct Context {
+    /// last id given to a list item
     pub id: usize,
+    pub modifiers: ModifierPolicy,
+    /// whether only safe link and image addresses are kept
+    pub safe: bool,
+    /// current nesting of quotes and lists
+    pub depth: usize,
+    pub max_depth: usize,
+    /// problems found while building text, taken after each file
+    pub errors: Vec<ParseError>,
+}
+
+impl Context {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            id: 0,
+            modifiers: options.modifiers,
+            safe: options.safe.is_some(),
+            depth: 0,
+            max_depth: options.max_depth(),
+            errors: vec![],
+        }
+    }
+
+    /// Whether `address` is removed by the safe mode, which is reported.
+    fn unsafe_address(&mut self, address: &str, span: Span) -> bool {
+        if !self.safe || is_safe_address(address) {
+            return false;
+        }
+        self.errors.push(ParseError::new(
+            ParseErrorKind::UnsafeAddress(address.to_string()),
+            span,
+        ));
+        true
+    }
+
+    /// Whether raw HTML is kept as text by the safe mode, which is reported.
+    fn raw_html_disabled(&mut self, span: Span) -> bool {
+        if self.safe {
+            self.errors
+                .push(ParseError::new(ParseErrorKind::RawHtml, span));
+        }
+        self.safe
+    }
+
+    /// Enters a quote or list starting at `span`, leave with `depth -= 1`.
+    fn enter(&mut self, span: Span) -> Result<(), Error> {
+        if self.depth == self.max_depth {
+            return Err(Error::NestingTooDeep {
+                file: None,
+                span,
+                max: self.max_depth,
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// The problems found since the last call as diagnostics of `file`, or the
+    /// error if the policy doesn't allow them.
+    pub(crate) fn take_errors(&mut self, file: Option<&Path>) -> Result<Vec<Diagnostic>, Error> {
+        let errors = std::mem::take(&mut self.errors);
+        let file = file.map(Path::to_path_buf);
+        let modifier = |error: &ParseError| {
+            matches!(
+                error.kind,
+                ParseErrorKind::UnclosedModifier(_) | ParseErrorKind::MismatchedModifier { .. }
+            )
+        };
+        if self.modifiers == ModifierPolicy::Error && errors.iter().any(modifier) {
+            return Err(Error::Parse { file, errors });
+        }
+        Ok(errors
+            .into_iter()
+            .map(|error| Diagnostic::Parse {
+                file: file.clone(),
+                error,
+            })
+            .collect())
+    }
+}
+
+pub(crate) fn generate_content(
+    turbo: &Vec<Turbo>,
+    context: &mut Context,
+) -> Result<Vec<TurboTree>, Error> {
+    let mut content = vec![];
+    let mut idx = 0;
+    while idx < turbo.len() {
+        // indented text outside of a list stands on its own
+        let ident = turbo[idx].line().map_or(0, |(ident, _)| *ident);
+        let (next_idx, next) = generate_recursive(turbo, idx, ident, None, context)?;
+        let (next_idx, next) = captioned(turbo, next_idx, next, context);
+        content.extend(next);
+        idx = next_idx;
+    }
+    Ok(content)
 }
 
 fn generate_recursive(
@@ -103,50 +514,68 @@ fn generate_recursive(
     mut current: usize,
     current_ident: usize,
     list_setting: Option<ListSetting>,
-    id_serial: &mut IdSerial,
-) -> (usize, Option<TurboTree>) {
+    context: &mut Context,
+) -> Result<(usize, Option<TurboTree>), Error> {
     let item = match &turbo[current] {
-        Turbo::Header { ident, size, text } => {
-            if list_setting.is_some() {
-                if *ident <= current_ident {
-                    return (current + 1, None);
-                }
+        Turbo::Header {
+            ident,
+            size,
+            numbered,
+            text,
+            span,
+        } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current + 1, None));
             }
 
+            let mut text = turbo_text(text, *span, context);
+            // unique ids are assigned once includes are resolved
+            let id = take_explicit_id(&mut text).unwrap_or_default();
             TurboTree::Heading {
                 size: *size,
-                text: turbo_text(text),
+                text,
+                id,
+                numbered: *numbered,
+                number: None,
+                span: *span,
             }
         }
-        Turbo::Horizontal { ident } => {
-            if list_setting.is_some() {
-                if *ident <= current_ident {
-                    return (current, None);
-                }
+        Turbo::Horizontal { ident, span } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current, None));
             }
-            TurboTree::Horizontal
+            TurboTree::Horizontal { span: *span }
         }
 
-        Turbo::Empty => TurboTree::Empty,
-        Turbo::Line { ident, text } => {
+        Turbo::Empty { span } => TurboTree::Empty { span: *span },
+        Turbo::Line { ident, text, span } => {
             if list_setting.is_some() {
                 if *ident <= current_ident {
-                    return (current + 1, None);
+                    return Ok((current + 1, None));
                 }
             } else {
                 if *ident != current_ident {
-                    return (current + 1, None);
+                    return Ok((current + 1, None));
                 }
             }
 
-            let mut text = turbo_text(text);
+            let mut text = turbo_text(text, *span, context);
+            let mut last = *span;
 
             let mut idx = current + 1;
             while idx < turbo.len() {
                 if let Some((next_ident, next_text)) = turbo[idx].line() {
                     if next_ident == ident {
-                        text.get_vec_mut().push(TurboText::Plain(" ".to_string()));
-                        turbo_text_extend(&mut text, next_text);
+                        let next_span = turbo[idx].span();
+                        // the joining space stands for the line break
+                        let space =
+                            Span::new(text.span().end, raw_span(next_text, next_span).start);
+                        text.push(TurboText::Plain {
+                            text: " ".to_string(),
+                            span: space,
+                        });
+                        turbo_text_extend(&mut text, next_text, next_span, context);
+                        last = next_span;
                     } else {
                         break;
                     }
@@ -156,28 +585,48 @@ fn generate_recursive(
                 }
             }
             current = idx;
-            return (current, Some(TurboTree::Text(text)));
+            return Ok((
+                current,
+                Some(TurboTree::Text {
+                    text,
+                    span: span.join(last),
+                }),
+            ));
         }
         Turbo::ListElemStart {
             ident,
             kind,
+            number,
+            marker,
             check,
             content,
+            span,
         } => {
+            let (kind, number) = &match &list_setting {
+                Some(setting) if *ident == current_ident => setting.next(kind, *number),
+                _ => (kind.clone(), *number),
+            };
             if let Some(setting) = &list_setting {
-                if *ident == current_ident && setting.nesting_counter > 1 {
-                    return (current, None);
+                if *ident == current_ident
+                    && (setting.nesting_counter > 1 || !setting.kind.continued_by(kind))
+                {
+                    return Ok((current, None));
                 }
                 if *ident < current_ident {
-                    return (current, None);
+                    return Ok((current, None));
                 }
             }
             let mut items = vec![];
+            // the following items of the same list don't nest any deeper
+            let opens_list = list_setting.is_none() || *ident != current_ident;
+            if opens_list {
+                context.enter(*span)?;
+            }
 
-            let (_, label) = generate_recursive(content, 0, 0, None, id_serial);
+            let (_, label) = generate_recursive(content, 0, 0, None, context)?;
 
             let nc = if let Some(setting) = &list_setting {
-                if *ident == current_ident && &setting.kind == kind {
+                if *ident == current_ident && setting.kind.continued_by(kind) {
                     setting.nesting_counter
                 } else {
                     0
@@ -195,9 +644,11 @@ fn generate_recursive(
                     Some(ListSetting {
                         kind: kind.clone(),
                         nesting_counter: nc + 1,
+                        number: *number,
                     }),
-                    id_serial,
-                );
+                    context,
+                )?;
+                let (next_idx, next) = captioned(turbo, next_idx, next, context);
                 if let Some(next) = next {
                     items.push(next);
                     idx = next_idx
@@ -207,20 +658,23 @@ fn generate_recursive(
             }
 
             current = idx;
-            id_serial.id += 1;
-
-            if let Some(_) = &list_setting {
-                if *ident == current_ident {
-                    return (
-                        current,
-                        Some(TurboTree::ListItem {
-                            id: id_serial.id,
-                            check: *check,
-                            label: Box::new(label),
-                            items,
-                        }),
-                    );
-                }
+            context.id += 1;
+            if opens_list {
+                context.depth -= 1;
+            }
+
+            if list_setting.is_some() && *ident == current_ident {
+                return Ok((
+                    current,
+                    Some(TurboTree::ListItem {
+                        id: context.id,
+                        marker: marker.clone(),
+                        check: *check,
+                        label: Box::new(label),
+                        span: spanning(*span, &items),
+                        items,
+                    }),
+                ));
             }
 
             let mut split_index = 0;
@@ -232,98 +686,443 @@ fn generate_recursive(
             }
             let mut rest = items.split_off(split_index);
             let list_item = TurboTree::ListItem {
-                id: id_serial.id,
+                id: context.id,
+                marker: marker.clone(),
                 check: *check,
                 label: Box::new(label),
+                span: spanning(*span, &items),
                 items,
             };
             rest.insert(0, list_item);
-            return (
+            return Ok((
                 current,
                 Some(TurboTree::List {
                     kind: kind.clone(),
+                    start: *number,
+                    span: spanning(*span, &rest),
                     items: rest,
                 }),
-            );
+            ));
         }
 
-        Turbo::Code { ident, code } => {
+        Turbo::Code { ident, code, span } => {
             if list_setting.is_some() {
                 if let Some(ident) = ident {
                     if *ident <= current_ident {
-                        return (current, None);
+                        return Ok((current, None));
                     }
                 }
             }
-            TurboTree::Code(code.clone())
+            let mut code = code.clone();
+            // shown as a code block instead
+            if code.lang == Lang::Html && context.raw_html_disabled(*span) {
+                code.lang = Lang::Other("html".to_string());
+            }
+            TurboTree::Code { code, span: *span }
         }
 
-        Turbo::Include { ident, path } => {
-            if list_setting.is_some() {
-                if *ident <= current_ident {
-                    return (current, None);
+        Turbo::Include { ident, path, span } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current, None));
+            }
+            if path == "toc" {
+                TurboTree::Toc {
+                    entries: vec![],
+                    span: *span,
+                }
+            } else {
+                TurboTree::Include {
+                    path: path.clone(),
+                    span: *span,
                 }
             }
-            TurboTree::Include(path.clone())
         }
-        Turbo::Root(_) => {
-            panic!("Illegal Root")
+        Turbo::Quote {
+            ident,
+            content,
+            attribution,
+            span,
+        } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current, None));
+            }
+            context.enter(*span)?;
+            let content = generate_content(content, context)?;
+            context.depth -= 1;
+            TurboTree::Quote {
+                content,
+                attribution: attribution
+                    .as_ref()
+                    .map(|text| turbo_text(text, *span, context)),
+                span: *span,
+            }
         }
+        Turbo::FootnoteDef {
+            ident,
+            name,
+            text,
+            span,
+        } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current, None));
+            }
+            TurboTree::FootnoteDef {
+                name: name.clone(),
+                text: turbo_text(text, *span, context),
+                span: *span,
+            }
+        }
+        Turbo::Table {
+            ident,
+            header,
+            alignments,
+            rows,
+            span,
+        } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current, None));
+            }
+            TurboTree::Table {
+                header: table_rows(header, *span, context),
+                alignments: alignments.clone(),
+                rows: table_rows(rows, *span, context),
+                span: *span,
+            }
+        }
+        Turbo::Caption { ident, text, span } => {
+            if list_setting.is_some() && *ident <= current_ident {
+                return Ok((current + 1, None));
+            }
+            // not after anything that can have a caption
+            context
+                .errors
+                .push(ParseError::new(ParseErrorKind::StrayCaption, *span));
+            TurboTree::Text {
+                text: turbo_text(text, *span, context),
+                span: *span,
+            }
+        }
+        Turbo::Root { .. } => return Err(StructureError::NestedRoot.into()),
     };
 
-    (current + 1, Some(item))
+    Ok((current + 1, Some(item)))
+}
+
+/// Wraps `node` into a figure if the caption line at `idx` follows it.
+fn captioned(
+    turbo: &[Turbo],
+    idx: usize,
+    node: Option<TurboTree>,
+    context: &mut Context,
+) -> (usize, Option<TurboTree>) {
+    let (Some(Turbo::Caption { text, span, .. }), Some(kind)) =
+        (turbo.get(idx), node.as_ref().and_then(FigureKind::of))
+    else {
+        return (idx, node);
+    };
+    let content = node.into_iter().collect::<Vec<_>>();
+    let mut caption = turbo_text(text, *span, context);
+    let id = take_explicit_id(&mut caption);
+    let caption = (!plain_text(&caption).trim().is_empty()).then_some(caption);
+    let figure = TurboTree::Figure {
+        kind,
+        id,
+        number: None,
+        caption,
+        span: content[0].span().join(*span),
+        content,
+    };
+    (idx + 1, Some(figure))
 }
 
-fn turbo_text_extend(to_extend: &mut TurboText, raw: &TurboTextRaw) {
-    turbo_text_recursive(to_extend, raw, 0, &mut HashSet::new());
+/// From the start of `span` to the end of the last node.
+fn spanning(span: Span, nodes: &[TurboTree]) -> Span {
+    nodes.last().map_or(span, |last| span.join(last.span()))
 }
 
-fn turbo_text(raw: &TurboTextRaw) -> TurboText {
-    let mut tt = TurboText::TextContainer(vec![]);
-    turbo_text_recursive(&mut tt, raw, 0, &mut HashSet::new());
+/// Span of the inline text, `fallback` if there is none.
+fn raw_span(raw: &TurboTextRaw, fallback: Span) -> Span {
+    match (raw.first(), raw.last()) {
+        (Some(first), Some(last)) => first.span().join(last.span()),
+        _ => fallback,
+    }
+}
+
+fn table_rows(
+    rows: &[Vec<TableCellRaw>],
+    span: Span,
+    context: &mut Context,
+) -> Vec<Vec<TableCell>> {
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| TableCell {
+                    text: turbo_text(&cell.text, span, context),
+                    colspan: cell.colspan,
+                    rowspan: cell.rowspan,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn turbo_text_extend(
+    to_extend: &mut TurboText,
+    raw: &TurboTextRaw,
+    span: Span,
+    context: &mut Context,
+) {
+    build_text(to_extend, raw, context);
+    to_extend.span_mut().end = raw_span(raw, span).end;
+}
+
+/// `span` is used for empty text, which has no span of its own.
+fn turbo_text(raw: &TurboTextRaw, span: Span, context: &mut Context) -> TurboText {
+    let mut tt = TurboText::TextContainer {
+        text: vec![],
+        span: raw_span(raw, span),
+    };
+    build_text(&mut tt, raw, context);
     tt
 }
 
-fn turbo_text_recursive(
-    tt: &mut TurboText,
-    text: &TurboTextRaw,
-    mut current: usize,
-    stats: &mut HashSet<TurboTextMod>,
-) -> usize {
-    while current < text.len() {
-        match &text[current] {
-            TurboInlineRaw::NewLine => {
-                let vec = tt.get_vec_mut();
-                vec.push(TurboText::NewLine);
-            }
-            TurboInlineRaw::ModFlag(m) => {
-                if !stats.contains(m) {
-                    stats.insert(*m);
-                    let vec = tt.get_vec_mut();
-                    let mut modifier = TurboText::Mod {
-                        kind: *m,
-                        text: vec![],
-                    };
-                    current = turbo_text_recursive(&mut modifier, text, current + 1, stats);
-                    vec.push(modifier);
+/// What a modifier flag does after pairing.
+#[derive(Copy, Clone)]
+enum Flag {
+    Open,
+    /// closes the modifier opened by the flag at the index
+    Close(usize),
+    Literal,
+}
+
+/// Pairs the modifier flags of one line. Adjacent flags, like the closing
+/// `***` of `***a***`, form a run that closes the innermost open modifiers it
+/// has a flag for in any order. The other flags of a run close the innermost
+/// open modifier of their kind, the modifiers opened after that one are
+/// mismatched, or open a new one. Flags that aren't paired are reported and
+/// handled by the policy.
+fn pair_flags(text: &TurboTextRaw, context: &mut Context) -> Vec<Flag> {
+    let mut flags = vec![Flag::Literal; text.len()];
+    let mut open: Vec<(TurboTextMod, usize)> = vec![];
+    let mut unpaired = vec![];
+    for run in flag_runs(text) {
+        // pairs for the most modifiers, closing none always works
+        let (keep, pairs, rest) = (0..=open.len())
+            .rev()
+            .find_map(|count| {
+                let keep = open.len() - count;
+                let mut rest = run.clone();
+                let pairs = open[keep..]
+                    .iter()
+                    .map(|(kind, opening)| {
+                        let position = rest.iter().position(|(flag, _)| flag == kind)?;
+                        Some((*opening, rest.remove(position).1))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((keep, pairs, rest))
+            })
+            .unwrap_or_default();
+        open.truncate(keep);
+        for (opening, idx) in pairs {
+            flags[opening] = Flag::Open;
+            flags[idx] = Flag::Close(opening);
+        }
+
+        // modifiers opened by the run can't be closed by it
+        let mut opened = vec![];
+        for (kind, idx) in rest {
+            let Some(position) = open.iter().rposition(|(open, _)| *open == kind) else {
+                opened.push((kind, idx));
+                continue;
+            };
+            for (inner, inner_idx) in open.split_off(position + 1) {
+                context.errors.push(ParseError::new(
+                    ParseErrorKind::MismatchedModifier {
+                        kind: inner,
+                        closed_by: kind,
+                    },
+                    text[inner_idx].span(),
+                ));
+                unpaired.push(inner_idx);
+            }
+            if let Some((_, opening)) = open.pop() {
+                flags[opening] = Flag::Open;
+                flags[idx] = Flag::Close(opening);
+            }
+        }
+        open.extend(opened);
+    }
+    for (kind, idx) in open {
+        context.errors.push(ParseError::new(
+            ParseErrorKind::UnclosedModifier(kind),
+            text[idx].span(),
+        ));
+        unpaired.push(idx);
+    }
+    if context.modifiers != ModifierPolicy::Literal {
+        unpaired.into_iter().for_each(|idx| flags[idx] = Flag::Open);
+    }
+    flags
+}
+
+/// The modifier flags of the text grouped into runs of adjacent flags.
+fn flag_runs(text: &TurboTextRaw) -> Vec<Vec<(TurboTextMod, usize)>> {
+    let mut runs: Vec<Vec<(TurboTextMod, usize)>> = vec![];
+    for (idx, inline) in text.iter().enumerate() {
+        let TurboInlineRaw::ModFlag { kind, .. } = inline else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some(run) if run.last().is_some_and(|(_, last)| last + 1 == idx) => {
+                run.push((*kind, idx))
+            }
+            _ => runs.push(vec![(*kind, idx)]),
+        }
+    }
+    runs
+}
+
+/// The order the text is built in, with the span of the whole run for flags.
+/// A run closes innermost first, before it opens anything.
+fn build_order(text: &TurboTextRaw, flags: &[Flag]) -> Vec<(usize, Span)> {
+    let mut runs = flag_runs(text).into_iter().peekable();
+    let mut order = vec![];
+    for (idx, inline) in text.iter().enumerate() {
+        if !matches!(inline, TurboInlineRaw::ModFlag { .. }) {
+            order.push((idx, inline.span()));
+            continue;
+        }
+        let Some(run) = runs.next_if(|run| run[0].1 == idx) else {
+            continue;
+        };
+        let span = text[run[0].1]
+            .span()
+            .join(text[run[run.len() - 1].1].span());
+        let mut run = run.into_iter().map(|(_, idx)| idx).collect::<Vec<_>>();
+        run.sort_by_key(|&idx| match flags[idx] {
+            Flag::Close(opening) => (0, usize::MAX - opening),
+            Flag::Open | Flag::Literal => (1, idx),
+        });
+        order.extend(run.into_iter().map(|idx| (idx, span)));
+    }
+    order
+}
+
+/// Appends the text to `tt`, with the flags paired into modifiers.
+fn build_text(tt: &mut TurboText, text: &TurboTextRaw, context: &mut Context) {
+    let flags = pair_flags(text, context);
+    // modifiers that are still open with the index of their flag, innermost last
+    let mut open: Vec<(usize, TurboText)> = vec![];
+    let push = |open: &mut Vec<(usize, TurboText)>, tt: &mut TurboText, node| match open.last_mut()
+    {
+        Some((_, parent)) => parent.push(node),
+        None => tt.push(node),
+    };
+    for (idx, run) in build_order(text, &flags) {
+        let node = match &text[idx] {
+            TurboInlineRaw::ModFlag { kind, span } => match flags[idx] {
+                Flag::Open => {
+                    open.push((
+                        idx,
+                        TurboText::Mod {
+                            kind: *kind,
+                            text: vec![],
+                            span: *span,
+                        },
+                    ));
+                    continue;
+                }
+                Flag::Close(opening) => {
+                    // closes the modifiers opened after this one as well
+                    while let Some((flag, mut modifier)) = open.pop() {
+                        let closed = flag == opening;
+                        modifier.span_mut().end = if closed { run.end } else { run.start };
+                        push(&mut open, tt, modifier);
+                        if closed {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                Flag::Literal => TurboText::Plain {
+                    text: kind.as_str().to_string(),
+                    span: *span,
+                },
+            },
+            TurboInlineRaw::NewLine { span } => TurboText::NewLine { span: *span },
+            TurboInlineRaw::Link {
+                alias,
+                address,
+                span,
+            } => {
+                if context.unsafe_address(address, *span) {
+                    TurboText::Plain {
+                        text: alias.clone().unwrap_or_else(|| address.clone()),
+                        span: *span,
+                    }
                 } else {
-                    stats.remove(m);
-                    return current;
+                    TurboText::Link {
+                        alias: alias.clone(),
+                        address: address.clone(),
+                        span: *span,
+                    }
                 }
             }
-            TurboInlineRaw::Link { alias, address } => {
-                tt.get_vec_mut().push(TurboText::Link {
-                    alias: alias.clone(),
-                    address: address.clone(),
-                });
+            TurboInlineRaw::Image { image, span } => {
+                if context.unsafe_address(&image.address, *span) {
+                    TurboText::Plain {
+                        text: image.alt.clone().unwrap_or_default(),
+                        span: *span,
+                    }
+                } else {
+                    TurboText::Image {
+                        image: image.clone(),
+                        span: *span,
+                    }
+                }
             }
-            TurboInlineRaw::Text(p) => {
-                tt.get_vec_mut().push(TurboText::Plain(p.clone()));
+            TurboInlineRaw::FootnoteRef { name, span } => TurboText::FootnoteRef {
+                name: name.clone(),
+                number: None,
+                occurrence: 0,
+                span: *span,
+            },
+            TurboInlineRaw::FigureRef { id, span } => TurboText::FigureRef {
+                id: id.clone(),
+                number: None,
+                span: *span,
+            },
+            TurboInlineRaw::Text { text, span } => TurboText::Plain {
+                text: text.clone(),
+                span: *span,
+            },
+            TurboInlineRaw::Math { math, span } => TurboText::Math {
+                math: math.clone(),
+                span: *span,
+            },
+            TurboInlineRaw::Html { html, span } => {
+                if context.raw_html_disabled(*span) {
+                    TurboText::Plain {
+                        text: html.clone(),
+                        span: *span,
+                    }
+                } else {
+                    TurboText::Html {
+                        html: html.clone(),
+                        span: *span,
+                    }
+                }
             }
+        };
+        push(&mut open, tt, node);
+    }
+    // the rest runs to the end of the line
+    while let Some((_, mut modifier)) = open.pop() {
+        if let Some(last) = text.last() {
+            modifier.span_mut().end = last.span().end;
         }
-        current += 1
+        push(&mut open, tt, modifier);
     }
-    current
 }
 
 impl fmt::Display for TurboTree {
@@ -337,31 +1136,44 @@ impl TurboTree {
         let mut buffer = String::new();
         let whitespace = |level: usize| (0..level * 2).map(|_| ' ').collect::<String>();
         match self {
-            TurboTree::Root { content } => {
+            TurboTree::Root { content, .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Root:\n");
                 for part in content {
                     buffer.push_str(&part.pretty_string(level + 1))
                 }
             }
-            TurboTree::Text(text) => {
+            TurboTree::Text { text, .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Text:\n");
                 buffer.push_str(&format!("{}{:?}", whitespace(level + 1), text));
-                buffer.push_str("\n");
+                buffer.push('\n');
             }
-            TurboTree::Heading { size, text } => {
+            TurboTree::Heading {
+                size,
+                text,
+                id,
+                number,
+                ..
+            } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Heading:\n");
                 buffer.push_str(&format!("{}size: {}\n", whitespace(level + 1), size));
+                buffer.push_str(&format!("{}id: {}\n", whitespace(level + 1), id));
+                if let Some(number) = number {
+                    buffer.push_str(&format!("{}number: {}\n", whitespace(level + 1), number));
+                }
                 buffer.push_str(&format!("{}text:\n", whitespace(level + 1)));
                 buffer.push_str(&format!("{}{:?}", whitespace(level + 1), text));
-                buffer.push_str("\n");
+                buffer.push('\n');
             }
-            TurboTree::List { kind, items } => {
+            TurboTree::List {
+                kind, start, items, ..
+            } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("List:\n");
                 buffer.push_str(&format!("{}kind: {}\n", whitespace(level + 1), kind));
+                buffer.push_str(&format!("{}start: {}\n", whitespace(level + 1), start));
                 buffer.push_str(&format!("{}items:\n", whitespace(level + 1)));
                 for item in items {
                     buffer.push_str(&item.pretty_string(level + 2));
@@ -369,13 +1181,16 @@ impl TurboTree {
             }
             TurboTree::ListItem {
                 id,
+                marker,
                 check,
                 label: check_label,
                 items,
+                ..
             } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("List Item:\n");
                 buffer.push_str(&format!("{}id: {}\n", whitespace(level + 1), id));
+                buffer.push_str(&format!("{}marker: {:?}\n", whitespace(level + 1), marker));
                 buffer.push_str(&format!("{}check: {:?}\n", whitespace(level + 1), check));
                 buffer.push_str(&format!(
                     "{}check: {:?}\n",
@@ -387,7 +1202,7 @@ impl TurboTree {
                     buffer.push_str(&item.pretty_string(level + 2))
                 }
             }
-            TurboTree::Code(code) => {
+            TurboTree::Code { code, .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Code:\n");
                 buffer.push_str(&format!("{}Lang: {:?}\n", whitespace(level + 1), code.lang));
@@ -398,19 +1213,103 @@ impl TurboTree {
                     buffer.push('\n');
                 }
             }
-            TurboTree::Horizontal => {
+            TurboTree::Quote {
+                content,
+                attribution,
+                ..
+            } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Quote:\n");
+                buffer.push_str(&format!(
+                    "{}attribution: {:?}\n",
+                    whitespace(level + 1),
+                    attribution
+                ));
+                buffer.push_str(&format!("{}content:\n", whitespace(level + 1)));
+                for part in content {
+                    buffer.push_str(&part.pretty_string(level + 2))
+                }
+            }
+            TurboTree::Table {
+                header,
+                alignments,
+                rows,
+                ..
+            } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Table:\n");
+                buffer.push_str(&format!("{}header:\n", whitespace(level + 1)));
+                for row in header {
+                    buffer.push_str(&format!("{}{:?}\n", whitespace(level + 2), row));
+                }
+                buffer.push_str(&format!(
+                    "{}alignments: {:?}\n",
+                    whitespace(level + 1),
+                    alignments
+                ));
+                buffer.push_str(&format!("{}rows:\n", whitespace(level + 1)));
+                for row in rows {
+                    buffer.push_str(&format!("{}{:?}\n", whitespace(level + 2), row));
+                }
+            }
+            TurboTree::FootnoteDef { name, text, .. } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Footnote Definition:\n");
+                buffer.push_str(&format!("{}name: {}\n", whitespace(level + 1), name));
+                buffer.push_str(&format!("{}{:?}\n", whitespace(level + 1), text));
+            }
+            TurboTree::Footnotes { footnotes, .. } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Footnotes:\n");
+                for footnote in footnotes {
+                    buffer.push_str(&format!("{}{:?}\n", whitespace(level + 1), footnote));
+                }
+            }
+            TurboTree::Horizontal { .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Horizontal\n");
             }
-            TurboTree::Empty => {
+            TurboTree::Empty { .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Empty:\n");
             }
-            TurboTree::Include(path) => {
+            TurboTree::Include { path, .. } => {
                 buffer.push_str(&whitespace(level));
                 buffer.push_str("Include:\n");
                 buffer.push_str(&format!("{}check: {:?}\n", whitespace(level + 1), path));
             }
+            TurboTree::Toc { entries, .. } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Toc:\n");
+                buffer.push_str(&format!(
+                    "{}entries: {}\n",
+                    whitespace(level + 1),
+                    entries.len()
+                ));
+            }
+            TurboTree::Figure {
+                kind,
+                id,
+                number,
+                caption,
+                content,
+                ..
+            } => {
+                buffer.push_str(&whitespace(level));
+                buffer.push_str("Figure:\n");
+                buffer.push_str(&format!("{}kind: {:?}\n", whitespace(level + 1), kind));
+                buffer.push_str(&format!("{}id: {:?}\n", whitespace(level + 1), id));
+                buffer.push_str(&format!("{}number: {:?}\n", whitespace(level + 1), number));
+                buffer.push_str(&format!(
+                    "{}caption: {:?}\n",
+                    whitespace(level + 1),
+                    caption
+                ));
+                buffer.push_str(&format!("{}content:\n", whitespace(level + 1)));
+                for part in content {
+                    buffer.push_str(&part.pretty_string(level + 2))
+                }
+            }
         }
         buffer
     }
@@ -420,17 +1319,166 @@ impl TurboTree {
 mod ast_tests {
     use super::*;
     use crate::parser::parser;
+    use crate::span::stream;
+    use crate::{parse_string, Position};
+    use chumsky::Parser;
 
     const INPUT: &str = "This is _a ~bad~ *super Test_ [hope](https://google.com) it _~*works*~_\n";
 
     #[test]
     fn test() {
-        let parse = parser().parse(INPUT).unwrap();
-        if let Turbo::Root(vec) = &parse {
-            if let Turbo::Line { text, .. } = &vec[0] {
-                let text = turbo_text(text);
+        let parse = parser().parse(stream(INPUT)).unwrap();
+        if let Turbo::Root { content, .. } = &parse {
+            if let Turbo::Line { text, span, .. } = &content[0] {
+                let text = turbo_text(text, *span, &mut Context::new(&Options::default()));
                 println!("{:?}", text);
             }
         }
     }
+
+    #[test]
+    fn modifier_policies() {
+        let generate = |modifiers| {
+            let options = Options {
+                modifiers,
+                ..Options::default()
+            };
+            let (parse, _) = parse_string("*a _b* c ~d\n").unwrap();
+            TurboTree::generate_with_options(parse, &options)
+                .map(|(tree, diagnostics)| (tree.generate_html(None).unwrap(), diagnostics))
+        };
+        let errors = |diagnostics: Vec<Diagnostic>| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| match diagnostic {
+                    Diagnostic::Parse { error, .. } => (error.kind, error.span.range()),
+                    other => panic!("unexpected {other:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            (
+                ParseErrorKind::MismatchedModifier {
+                    kind: TurboTextMod::Sub,
+                    closed_by: TurboTextMod::Bold,
+                },
+                3..4,
+            ),
+            (
+                ParseErrorKind::UnclosedModifier(TurboTextMod::Strike),
+                9..10,
+            ),
+        ];
+
+        let (html, diagnostics) = generate(ModifierPolicy::Literal).unwrap();
+        assert_eq!(html, "<p><b>a _b</b> c ~d</p>\n");
+        assert_eq!(errors(diagnostics), expected);
+
+        let (html, diagnostics) = generate(ModifierPolicy::AutoClose).unwrap();
+        assert_eq!(html, "<p><b>a <sub>b</sub></b> c <del>d</del></p>\n");
+        assert_eq!(errors(diagnostics), expected);
+
+        match generate(ModifierPolicy::Error) {
+            Err(Error::Parse { file: None, errors }) => assert_eq!(
+                errors
+                    .into_iter()
+                    .map(|error| (error.kind, error.span.range()))
+                    .collect::<Vec<_>>(),
+                expected
+            ),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn adjacent_flags_close_in_any_order() {
+        let input = "***x***\n\n*a **b***\n\nHello this *is* a ***test***\n";
+        let (parse, _) = parse_string(input).unwrap();
+        let (tree, diagnostics) = TurboTree::generate_with_diagnostics(parse).unwrap();
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(
+            tree.generate_html(None).unwrap(),
+            "<p><i><b>x</b></i></p>\n\
+             <p><b>a <i>b</i></b></p>\n\
+             <p>Hello this <b>is</b> a <i><b>test</b></i></p>\n"
+        );
+        // both modifiers end with the run that closes them
+        let TurboTree::Text { text, .. } = &tree.get_vec().unwrap()[0] else {
+            panic!("expected text")
+        };
+        let TurboText::TextContainer { text, .. } = text else {
+            panic!("expected container")
+        };
+        let TurboText::Mod {
+            text: inner, span, ..
+        } = &text[0]
+        else {
+            panic!("expected modifier")
+        };
+        assert_eq!((span.range(), inner[0].span().range()), (0..7, 2..7));
+    }
+
+    #[test]
+    fn letters_continue_alphabetic_lists() {
+        let input = "- h. a\n- i. b\n- j. c\n\n- U. d\n- V. e\n\n- a. f\n- i. g\n";
+        let tree = TurboTree::generate(parse_string(input).unwrap().0).unwrap();
+        let lists = tree
+            .get_vec()
+            .unwrap()
+            .iter()
+            .filter_map(|node| match node {
+                TurboTree::List {
+                    kind, start, items, ..
+                } => Some((kind.clone(), *start, items.len())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lists,
+            vec![
+                (ListKind::AlphabetLower, 8, 3),
+                (ListKind::AlphabetUpper, 21, 2),
+                // `i` isn't the letter after `a`
+                (ListKind::AlphabetLower, 1, 1),
+                (ListKind::RomanLower, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let input = "# Head\n\nsome *bold*\ntext\n- item\n  - nested\n";
+        let tree = TurboTree::generate(parse_string(input).unwrap().0).unwrap();
+        let range = |node: &TurboTree| node.span().range();
+        let content = tree.get_vec().unwrap();
+        assert_eq!(range(&content[0]), 0..7);
+
+        let TurboTree::Text { text, span } = &content[2] else {
+            panic!("expected text")
+        };
+        assert_eq!(span.range(), 8..25);
+        assert_eq!(
+            span.end,
+            Position {
+                offset: 25,
+                line: 5,
+                column: 1
+            }
+        );
+        let parts = match text {
+            TurboText::TextContainer { text, .. } => text,
+            _ => panic!("expected container"),
+        };
+        let ranges = parts
+            .iter()
+            .map(|text| text.span().range())
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![8..13, 13..19, 19..20, 20..24]);
+        assert_eq!(&input[13..19], "*bold*");
+
+        assert_eq!(range(&content[3]), 25..43);
+        let item = &content[3].get_vec().unwrap()[0];
+        assert_eq!(range(item), 25..43);
+        assert_eq!(range(&item.get_vec().unwrap()[0]), 32..43);
+    }
 }
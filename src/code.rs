@@ -0,0 +1,122 @@
+use crate::ast::TurboTree;
+use crate::visit::{fold_content, fold_tree_children, Fold};
+use crate::{Code, Lang};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a code handler turns a code block into.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeOutput {
+    /// Nodes that take the place of the block.
+    Nodes(Vec<TurboTree>),
+    /// Markup that is put into the HTML as it is.
+    Html(String),
+}
+
+type Handler = Arc<dyn Fn(&str) -> CodeOutput + Send + Sync>;
+
+/// Handlers for code blocks of languages the crate doesn't know, by language
+/// name. Blocks without a handler are rendered as `<pre><code>`.
+#[derive(Clone, Default)]
+pub struct CodeHandlers {
+    handlers: HashMap<String, Handler>,
+}
+
+impl CodeHandlers {
+    /// Registers `handler` for blocks of the language `lang`, replacing the
+    /// previous handler of that language.
+    pub fn register(
+        &mut self,
+        lang: impl Into<String>,
+        handler: impl Fn(&str) -> CodeOutput + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.handlers.insert(lang.into(), Arc::new(handler));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Replaces every code block that has a handler with the handler's output.
+    pub(crate) fn apply(&self, content: &mut Vec<TurboTree>) {
+        if !self.is_empty() {
+            *content = fold_content(&mut Apply(self), std::mem::take(content));
+        }
+    }
+}
+
+struct Apply<'a>(&'a CodeHandlers);
+
+impl Fold for Apply<'_> {
+    fn fold_tree(&mut self, node: TurboTree) -> Vec<TurboTree> {
+        let TurboTree::Code {
+            code:
+                Code {
+                    lang: Lang::Other(lang),
+                    code,
+                },
+            span,
+        } = &node
+        else {
+            return vec![fold_tree_children(self, node)];
+        };
+        let Some(handler) = self.0.handlers.get(lang.as_str()) else {
+            return vec![node];
+        };
+        match handler(code) {
+            CodeOutput::Nodes(nodes) => nodes,
+            CodeOutput::Html(html) => vec![TurboTree::Code {
+                code: Code {
+                    lang: Lang::Html,
+                    code: html,
+                },
+                span: *span,
+            }],
+        }
+    }
+}
+
+impl fmt::Debug for CodeHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod code_tests {
+    use super::*;
+    use crate::{parse_string, Options};
+
+    fn generate(input: &str, options: &Options) -> String {
+        let (parse, _) = parse_string(input).unwrap();
+        let (tree, _) = TurboTree::generate_with_options(parse, options).unwrap();
+        tree.generate_html(None).unwrap()
+    }
+
+    #[test]
+    fn handlers() {
+        let mut options = Options::default();
+        options
+            .code_handlers
+            .register("shout", |code| {
+                let (parse, _) = parse_string(&code.to_uppercase()).unwrap();
+                match TurboTree::generate(parse).unwrap() {
+                    TurboTree::Root { content, .. } => CodeOutput::Nodes(content),
+                    _ => unreachable!(),
+                }
+            })
+            .register("widget", |code| {
+                CodeOutput::Html(format!("<x-widget>{}</x-widget>\n", code.trim()))
+            });
+        let input = "::: shout\nhey\n:::\n> ::: widget\n> w\n> :::\n::: zig\nconst\n:::\n";
+        assert_eq!(
+            generate(input, &options),
+            "<p>HEY</p>\n<blockquote>\n<x-widget>w</x-widget>\n</blockquote>\n\
+             <pre><code class=\"language-zig\">\nconst\n</code></pre>\n"
+        );
+        // unhandled blocks are kept as code
+        assert!(generate(input, &Options::default()).contains("class=\"language-shout\""));
+    }
+}
@@ -1,4 +1,6 @@
 use clap::Parser;
+use std::path::PathBuf;
+use turbo_md::{dotted, ModifierPolicy};
 
 #[derive(Debug, Parser)]
 #[clap(about = "Turbo-md parser, -h for help")]
@@ -8,16 +10,105 @@ pub struct Args {
 
     #[clap(arg_enum, default_value_t, required = false)]
     pub option: RunOption,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value_t,
+        help = "what happens to modifiers that aren't closed"
+    )]
+    pub modifiers: Modifiers,
+
+    #[clap(
+        long,
+        help = "restrict links, includes and input size for untrusted documents"
+    )]
+    pub safe: bool,
+
+    #[clap(long, help = "directory includes have to stay in, implies --safe")]
+    pub include_root: Option<PathBuf>,
+
+    #[clap(long, help = "add a link to its own anchor to every heading")]
+    pub permalinks: bool,
+
+    #[clap(
+        long,
+        help = "put a table of contents at the start if there is no @[toc]"
+    )]
+    pub toc: bool,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "size of the largest headings in the table of contents"
+    )]
+    pub toc_min: usize,
+
+    #[clap(
+        long,
+        default_value_t = 6,
+        help = "size of the smallest headings in the table of contents"
+    )]
+    pub toc_max: usize,
+
+    #[clap(long, help = "number headings like 1, 1.1 and 1.2.3")]
+    pub numbering: bool,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "size of the headings numbered on the first level"
+    )]
+    pub numbering_start: usize,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value_t,
+        help = "how heading numbers are written, 1.2.3, 1.2.3. or 3"
+    )]
+    pub numbering_format: NumberingFormat,
 }
 
-#[derive(Debug, clap::ArgEnum, Clone)]
+#[derive(Debug, Default, clap::ArgEnum, Clone)]
 pub enum RunOption {
+    #[default]
     Html,
     Ast,
 }
 
-impl Default for RunOption {
-    fn default() -> Self {
-        Self::Html
+#[derive(Debug, Default, clap::ArgEnum, Clone)]
+pub enum Modifiers {
+    Literal,
+    #[default]
+    AutoClose,
+    Error,
+}
+
+impl From<Modifiers> for ModifierPolicy {
+    fn from(value: Modifiers) -> Self {
+        match value {
+            Modifiers::Literal => ModifierPolicy::Literal,
+            Modifiers::AutoClose => ModifierPolicy::AutoClose,
+            Modifiers::Error => ModifierPolicy::Error,
+        }
+    }
+}
+
+#[derive(Debug, Default, clap::ArgEnum, Clone)]
+pub enum NumberingFormat {
+    #[default]
+    Dotted,
+    Trailing,
+    Last,
+}
+
+impl NumberingFormat {
+    pub fn format(&self) -> fn(&[usize]) -> String {
+        match self {
+            NumberingFormat::Dotted => dotted,
+            NumberingFormat::Trailing => |counters| format!("{}.", dotted(counters)),
+            NumberingFormat::Last => |counters| counters.last().unwrap_or(&0).to_string(),
+        }
     }
 }
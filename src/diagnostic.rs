@@ -0,0 +1,321 @@
+use crate::span::Span;
+use crate::TurboTextMod;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problems found while parsing and generating the tree. They never abort
+/// generation, the affected input is kept as well as possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A syntax problem in `file`, `None` for parsed strings.
+    Parse {
+        file: Option<PathBuf>,
+        error: ParseError,
+    },
+    /// A reference to a footnote without definition, `file` is `None` for
+    /// parsed strings.
+    UndefinedFootnote {
+        name: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// A definition no reference points to.
+    UnusedFootnote {
+        name: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// A definition of a footnote that is already defined, it is left out.
+    DuplicateFootnote {
+        name: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// Two headings with the same explicit `{#id}`, the later one gets a suffix.
+    DuplicateHeadingId {
+        id: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// Two figures with the same `{#label}`, the later one loses it.
+    DuplicateFigureId {
+        id: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// A `@label` reference no figure has.
+    UndefinedFigure {
+        id: String,
+        file: Option<PathBuf>,
+        span: Span,
+    },
+    /// A link in `file` points to a document that doesn't exist.
+    MissingLinkTarget {
+        file: PathBuf,
+        address: String,
+        span: Span,
+    },
+    /// A link in `file` points to a heading that doesn't exist.
+    MissingLinkAnchor {
+        file: PathBuf,
+        address: String,
+        span: Span,
+    },
+    /// A link in `file` points to a document that can't be generated, so its
+    /// headings are unknown.
+    UncheckedLinkTarget {
+        file: PathBuf,
+        address: String,
+        span: Span,
+        reason: String,
+    },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::Parse { error, .. } => error.kind.severity(),
+            _ => Severity::Warning,
+        }
+    }
+
+    /// The file and span the diagnostic points to, if it has a location.
+    pub fn location(&self) -> Option<(Option<&Path>, Span)> {
+        match self {
+            Diagnostic::Parse { file, error } => Some((file.as_deref(), error.span)),
+            Diagnostic::MissingLinkTarget { file, span, .. }
+            | Diagnostic::MissingLinkAnchor { file, span, .. }
+            | Diagnostic::UncheckedLinkTarget { file, span, .. } => Some((Some(file), *span)),
+            Diagnostic::UndefinedFootnote { file, span, .. }
+            | Diagnostic::UnusedFootnote { file, span, .. }
+            | Diagnostic::DuplicateFootnote { file, span, .. }
+            | Diagnostic::DuplicateHeadingId { file, span, .. }
+            | Diagnostic::DuplicateFigureId { file, span, .. }
+            | Diagnostic::UndefinedFigure { file, span, .. } => Some((file.as_deref(), *span)),
+        }
+    }
+
+    /// Sets the file of a diagnostic found once the includes are resolved,
+    /// `files` are the paths in the order the resolver read them.
+    pub(crate) fn locate(&mut self, files: &[PathBuf]) {
+        if let Diagnostic::UndefinedFootnote { file, span, .. }
+        | Diagnostic::UnusedFootnote { file, span, .. }
+        | Diagnostic::DuplicateFootnote { file, span, .. }
+        | Diagnostic::DuplicateHeadingId { file, span, .. }
+        | Diagnostic::DuplicateFigureId { file, span, .. }
+        | Diagnostic::UndefinedFigure { file, span, .. } = self
+        {
+            *file = files.get(span.file).cloned();
+        }
+    }
+}
+
+/// The message without the location, see [`Diagnostic::location`].
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::Parse { error, .. } => write!(f, "{}", error.kind),
+            Diagnostic::UndefinedFootnote { name, .. } => {
+                write!(f, "footnote `{name}` is referenced but never defined")
+            }
+            Diagnostic::UnusedFootnote { name, .. } => {
+                write!(f, "footnote `{name}` is defined but never referenced")
+            }
+            Diagnostic::DuplicateFootnote { name, .. } => {
+                write!(f, "footnote `{name}` is defined more than once")
+            }
+            Diagnostic::DuplicateHeadingId { id, .. } => {
+                write!(f, "heading id `{id}` is used more than once")
+            }
+            Diagnostic::DuplicateFigureId { id, .. } => {
+                write!(f, "figure label `{id}` is used more than once")
+            }
+            Diagnostic::UndefinedFigure { id, .. } => {
+                write!(f, "figure `{id}` is referenced but never labeled")
+            }
+            Diagnostic::MissingLinkTarget { address, .. } => {
+                write!(f, "link to `{address}`: document not found")
+            }
+            Diagnostic::MissingLinkAnchor { address, .. } => {
+                write!(f, "link to `{address}`: heading not found")
+            }
+            Diagnostic::UncheckedLinkTarget {
+                address, reason, ..
+            } => {
+                write!(f, "link to `{address}` is not checked: {reason}")
+            }
+        }
+    }
+}
+
+/// Error type of the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Input that doesn't fit anywhere, the line is skipped.
+    /// `None` stands for the end of the input.
+    Unexpected {
+        found: Option<char>,
+        expected: Vec<Option<char>>,
+    },
+    /// A modifier that isn't closed on its line.
+    UnclosedModifier(TurboTextMod),
+    /// A modifier that is still open when `closed_by`, opened before it, closes.
+    MismatchedModifier {
+        kind: TurboTextMod,
+        closed_by: TurboTextMod,
+    },
+    /// A `:::` block without its closing `:::`, it runs to the end of the file.
+    UnterminatedBlock,
+    /// A `[` that doesn't start a link or footnote reference, kept as text.
+    MalformedLink,
+    MissingAltText,
+    UnknownImageAttribute(String),
+    /// A `colspan` or `rowspan` of 0 in a table block.
+    ZeroSpan,
+    /// A link or image address removed by the safe mode, its text is kept.
+    UnsafeAddress(String),
+    /// A quote nested deeper than the safe mode allows.
+    NestingTooDeep,
+    /// Raw HTML in safe mode, it is kept as text.
+    RawHtml,
+    /// A caption line that doesn't follow an image, table or code block, it
+    /// is kept as text.
+    StrayCaption,
+    /// A line of a `::: katex-macros` block that isn't `\name: expansion`,
+    /// it is left out.
+    MalformedMacro,
+    /// Cells of a table row beyond the columns of the header, they are left out.
+    ExtraTableCells,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl ParseErrorKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            ParseErrorKind::Unexpected { .. }
+            | ParseErrorKind::UnterminatedBlock
+            | ParseErrorKind::NestingTooDeep => Severity::Error,
+            ParseErrorKind::UnclosedModifier(_)
+            | ParseErrorKind::MismatchedModifier { .. }
+            | ParseErrorKind::MalformedLink
+            | ParseErrorKind::MissingAltText
+            | ParseErrorKind::UnknownImageAttribute(_)
+            | ParseErrorKind::ZeroSpan
+            | ParseErrorKind::UnsafeAddress(_)
+            | ParseErrorKind::RawHtml
+            | ParseErrorKind::StrayCaption
+            | ParseErrorKind::MalformedMacro
+            | ParseErrorKind::ExtraTableCells => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = |c: &Option<char>| match c {
+            Some('\n') => "line break".to_string(),
+            Some(c) => format!("`{c}`"),
+            None => "end of input".to_string(),
+        };
+        match self {
+            ParseErrorKind::Unexpected { found, expected } => {
+                write!(f, "unexpected {}", token(found))?;
+                // the other line breaks and spaces only add noise
+                let expected = expected
+                    .iter()
+                    .filter(|c| c.is_none_or(|c| c == '\n' || c.is_ascii_graphic()))
+                    .map(token)
+                    .collect::<Vec<_>>();
+                if !expected.is_empty() && expected.len() <= 4 {
+                    write!(f, ", expected {}", expected.join(" or "))?;
+                }
+                Ok(())
+            }
+            ParseErrorKind::UnclosedModifier(kind) => {
+                write!(f, "`{}` is never closed", kind.as_str())
+            }
+            ParseErrorKind::MismatchedModifier { kind, closed_by } => write!(
+                f,
+                "`{}` must be closed before `{}`",
+                kind.as_str(),
+                closed_by.as_str()
+            ),
+            ParseErrorKind::UnterminatedBlock => write!(f, "`:::` block is never closed"),
+            ParseErrorKind::MalformedLink => {
+                write!(f, "malformed link, expected `[text](address)`")
+            }
+            ParseErrorKind::MissingAltText => write!(f, "image is missing alt text"),
+            ParseErrorKind::UnknownImageAttribute(key) => {
+                write!(f, "unknown image attribute `{key}`")
+            }
+            ParseErrorKind::ZeroSpan => write!(f, "span must be at least 1"),
+            ParseErrorKind::UnsafeAddress(address) => {
+                write!(f, "unsafe address `{address}` is removed")
+            }
+            ParseErrorKind::NestingTooDeep => write!(f, "quote is nested too deep"),
+            ParseErrorKind::RawHtml => write!(f, "raw HTML is disabled, it is kept as text"),
+            ParseErrorKind::StrayCaption => write!(
+                f,
+                "caption doesn't follow an image, table or code block, it is kept as text"
+            ),
+            ParseErrorKind::MalformedMacro => {
+                write!(f, "malformed macro, expected `\\name: expansion`")
+            }
+            ParseErrorKind::ExtraTableCells => {
+                write!(f, "row has more cells than the header, they are left out")
+            }
+        }
+    }
+}
+
+impl chumsky::Error<char> for ParseError {
+    type Span = Span;
+    type Label = &'static str;
+
+    fn expected_input_found<Iter: IntoIterator<Item = Option<char>>>(
+        span: Span,
+        expected: Iter,
+        found: Option<char>,
+    ) -> Self {
+        let mut expected = expected.into_iter().collect::<Vec<_>>();
+        expected.sort();
+        expected.dedup();
+        Self::new(ParseErrorKind::Unexpected { found, expected }, span)
+    }
+
+    fn with_label(self, _label: Self::Label) -> Self {
+        self
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        if let (
+            ParseErrorKind::Unexpected { expected, .. },
+            ParseErrorKind::Unexpected {
+                expected: other, ..
+            },
+        ) = (&mut self.kind, other.kind)
+        {
+            expected.extend(other);
+            expected.sort();
+            expected.dedup();
+        }
+        self
+    }
+}
@@ -0,0 +1,201 @@
+use crate::diagnostic::ParseError;
+use crate::span::Span;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+/// Errors that stop parsing or generation. Problems the output can be
+/// generated despite are reported as [`Diagnostic`](crate::Diagnostic)s.
+#[derive(Debug)]
+pub enum Error {
+    /// The file could not be read, `from` is the including file.
+    Io {
+        path: PathBuf,
+        from: Option<PathBuf>,
+        error: io::Error,
+    },
+    /// The parser could not recover and no tree was produced, or modifiers are
+    /// unbalanced with [`ModifierPolicy::Error`](crate::ModifierPolicy::Error).
+    /// `file` is `None` for parsed strings.
+    Parse {
+        file: Option<PathBuf>,
+        errors: Vec<ParseError>,
+    },
+    /// The chain of files that includes itself, the last one closes the cycle.
+    IncludeCycle(Vec<PathBuf>),
+    /// A node in a place the parser never puts it, e.g. in a tree built by hand.
+    Structure(StructureError),
+    /// An include that leaves the include root of the safe mode.
+    IncludeOutsideRoot { path: PathBuf, root: PathBuf },
+    /// A file, or a document with its includes, larger than the safe mode allows.
+    InputTooLarge {
+        file: Option<PathBuf>,
+        size: usize,
+        max: usize,
+    },
+    /// Quotes or lists nested deeper than the safe mode allows.
+    NestingTooDeep {
+        file: Option<PathBuf>,
+        span: Span,
+        max: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureError {
+    /// The operation needs a `Root` node.
+    NotRoot,
+    /// A `Root` inside of other content.
+    NestedRoot,
+    /// An `Include` in the tree that is rendered, includes are only resolved
+    /// when generating from a file.
+    UnresolvedInclude(String),
+    /// A list item label that is neither text nor a heading.
+    InvalidListLabel,
+    /// An unordered list style other than none, circle, disc or square.
+    UnknownListStyle(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, from, error } => {
+                write!(f, "cannot read `{}`", path.display())?;
+                if let Some(from) = from {
+                    write!(f, " included from `{}`", from.display())?;
+                }
+                write!(f, ": {error}")
+            }
+            Error::Parse { file, errors } => {
+                write!(f, "cannot parse")?;
+                if let Some(file) = file {
+                    write!(f, " `{}`", file.display())?;
+                }
+                match errors.first() {
+                    Some(error) => write!(f, ", {} at {}", error.kind, error.span),
+                    None => Ok(()),
+                }
+            }
+            Error::IncludeCycle(chain) => {
+                let chain = chain
+                    .iter()
+                    .map(|path| format!("`{}`", path.display()))
+                    .collect::<Vec<_>>();
+                write!(f, "include cycle: {}", chain.join(" -> "))
+            }
+            Error::Structure(error) => write!(f, "invalid tree: {error}"),
+            Error::IncludeOutsideRoot { path, root } => write!(
+                f,
+                "`{}` is outside of the include root `{}`",
+                path.display(),
+                root.display()
+            ),
+            Error::InputTooLarge { file, size, max } => {
+                write!(f, "input")?;
+                if let Some(file) = file {
+                    write!(f, " `{}`", file.display())?;
+                }
+                write!(f, " has {size} bytes, at most {max} are allowed")
+            }
+            Error::NestingTooDeep { file, span, max } => {
+                write!(f, "nested deeper than {max} levels at ")?;
+                if let Some(file) = file {
+                    write!(f, "{}:", file.display())?;
+                }
+                write!(f, "{span}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::NotRoot => write!(f, "expected a root node"),
+            StructureError::NestedRoot => write!(f, "a root node can't be nested"),
+            StructureError::UnresolvedInclude(path) => {
+                write!(f, "include of `{path}` is not resolved")
+            }
+            StructureError::InvalidListLabel => {
+                write!(f, "a list label must be text or a heading")
+            }
+            StructureError::UnknownListStyle(style) => write!(f, "unknown list style {style}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Sets the file of errors in a file, others are kept as they are.
+    pub(crate) fn in_file(mut self, path: &Path) -> Self {
+        if let Error::Parse { file, .. }
+        | Error::InputTooLarge { file, .. }
+        | Error::NestingTooDeep { file, .. } = &mut self
+        {
+            *file = Some(path.to_path_buf());
+        }
+        self
+    }
+}
+
+impl From<StructureError> for Error {
+    fn from(error: StructureError) -> Self {
+        Error::Structure(error)
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+    use crate::{parse_file, parse_string, Span, TurboTree};
+
+    fn generate(input: &str) -> TurboTree {
+        TurboTree::generate(parse_string(input).unwrap().0).unwrap()
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join("turbo-error-missing");
+        match parse_file(path.to_str().unwrap()) {
+            Err(Error::Io {
+                path: missing,
+                from: None,
+                ..
+            }) => assert_eq!(missing, path.with_extension("tmd")),
+            other => panic!("expected io error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn structural_errors() {
+        let text = TurboTree::Horizontal {
+            span: Span::default(),
+        };
+        assert!(matches!(
+            text.generate_html(None),
+            Err(Error::Structure(StructureError::NotRoot))
+        ));
+        assert!(text.get_vec().is_none());
+
+        // includes are only resolved from files
+        assert!(matches!(
+            generate("@[part]\n").generate_html(None),
+            Err(Error::Structure(StructureError::UnresolvedInclude(path))) if path == "part"
+        ));
+    }
+
+    #[test]
+    fn indented_text_outside_of_list() {
+        let html = generate("  indented\n  text\n")
+            .generate_html(None)
+            .unwrap();
+        assert_eq!(html, "<p>indented text</p>\n");
+    }
+}
@@ -0,0 +1,228 @@
+use crate::ast::{TurboText, TurboTree};
+use crate::span::Span;
+use crate::visit::{walk_text_mut, walk_tree, walk_tree_mut, Visitor, VisitorMut};
+use crate::{Diagnostic, Lang};
+use std::collections::{HashMap, HashSet};
+
+/// What a figure shows, each kind is numbered on its own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FigureKind {
+    /// An image, or a mermaid diagram.
+    Figure,
+    Table,
+    /// A code block.
+    Listing,
+    /// A `::: math` block.
+    Equation,
+}
+
+impl FigureKind {
+    /// The name captions and references start with, like "Figure 3".
+    pub fn name(&self) -> &'static str {
+        match self {
+            FigureKind::Figure => "Figure",
+            FigureKind::Table => "Table",
+            FigureKind::Listing => "Listing",
+            FigureKind::Equation => "Equation",
+        }
+    }
+
+    /// The kind of figure `node` becomes with a caption, `None` if it can't
+    /// have one. Text is a figure if it is a single image.
+    pub(crate) fn of(node: &TurboTree) -> Option<Self> {
+        match node {
+            TurboTree::Table { .. } => Some(FigureKind::Table),
+            TurboTree::Code { code, .. } => match code.lang {
+                Lang::KaTeX => Some(FigureKind::Equation),
+                Lang::KaTeXMacros => None,
+                Lang::Mermaid => Some(FigureKind::Figure),
+                _ => Some(FigureKind::Listing),
+            },
+            TurboTree::Text {
+                text: TurboText::TextContainer { text, .. },
+                ..
+            } => {
+                let mut images = 0;
+                for node in text {
+                    match node {
+                        TurboText::Image { .. } => images += 1,
+                        TurboText::Plain { text, .. } if text.trim().is_empty() => {}
+                        _ => return None,
+                    }
+                }
+                (images == 1).then_some(FigureKind::Figure)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Numbers the figures of each kind in document order and resolves the
+/// references to them. A repeated label is reported and only the first figure
+/// keeps it.
+pub(crate) fn resolve(content: &mut [TurboTree], diagnostics: &mut Vec<Diagnostic>) {
+    let mut numbers = Numbers::default();
+    content
+        .iter_mut()
+        .for_each(|node| numbers.visit_tree_mut(node));
+    diagnostics.extend(numbers.duplicates.into_iter().map(|(id, span)| {
+        Diagnostic::DuplicateFigureId {
+            id,
+            file: None,
+            span,
+        }
+    }));
+
+    let mut references = References {
+        labels: numbers.labels,
+        undefined: vec![],
+    };
+    content
+        .iter_mut()
+        .for_each(|node| references.visit_tree_mut(node));
+    diagnostics.extend(references.undefined.into_iter().map(|(id, span)| {
+        Diagnostic::UndefinedFigure {
+            id,
+            file: None,
+            span,
+        }
+    }));
+}
+
+/// The labels of the figures.
+pub(crate) fn ids(content: &[TurboTree]) -> HashSet<String> {
+    struct Ids(HashSet<String>);
+    impl Visitor for Ids {
+        fn visit_tree(&mut self, node: &TurboTree) {
+            if let TurboTree::Figure { id: Some(id), .. } = node {
+                self.0.insert(id.clone());
+            }
+            walk_tree(self, node)
+        }
+
+        fn visit_text(&mut self, _text: &TurboText) {}
+    }
+    let mut ids = Ids(HashSet::new());
+    content.iter().for_each(|node| ids.visit_tree(node));
+    ids.0
+}
+
+#[derive(Default)]
+struct Numbers {
+    /// last number given per kind
+    counters: HashMap<FigureKind, usize>,
+    labels: HashMap<String, (FigureKind, usize)>,
+    duplicates: Vec<(String, Span)>,
+}
+
+impl VisitorMut for Numbers {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        if let TurboTree::Figure {
+            kind,
+            id,
+            number,
+            span,
+            ..
+        } = node
+        {
+            let counter = self.counters.entry(*kind).or_default();
+            *counter += 1;
+            *number = Some(*counter);
+            match id {
+                Some(label) if self.labels.contains_key(label.as_str()) => {
+                    self.duplicates.push((label.clone(), *span));
+                    *id = None;
+                }
+                Some(label) => {
+                    self.labels.insert(label.clone(), (*kind, *counter));
+                }
+                None => {}
+            }
+        }
+        walk_tree_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, _text: &mut TurboText) {}
+}
+
+struct References {
+    labels: HashMap<String, (FigureKind, usize)>,
+    /// each undefined label once, with its first reference
+    undefined: Vec<(String, Span)>,
+}
+
+impl VisitorMut for References {
+    fn visit_text_mut(&mut self, text: &mut TurboText) {
+        if let TurboText::FigureRef { id, number, span } = text {
+            *number = self.labels.get(id).copied();
+            if number.is_none() && !self.undefined.iter().any(|(undefined, _)| undefined == id) {
+                self.undefined.push((id.clone(), *span));
+            }
+        }
+        walk_text_mut(self, text)
+    }
+}
+
+#[cfg(test)]
+mod figure_tests {
+    use crate::{parse_string, Diagnostic, TurboTree};
+
+    #[test]
+    fn numbers_and_references() {
+        let input = "See @fig:b, @tbl:a and @fig:x.\n\n\
+                     ![A](a.png)\n\
+                     : First {#fig:a}\n\
+                     | a |\n|---|\n| 1 |\n\
+                     : {#tbl:a}\n\
+                     > ![B](b.png) ![C](c.png)\n\
+                     > : Two images\n\
+                     > ![B](b.png)\n\
+                     > : Second {#fig:b}\n\
+                     ::: rust\nfn main() {}\n:::\n\
+                     : Entry {#fig:a}\n\
+                     : stray\n";
+        let (parse, _) = parse_string(input).unwrap();
+        let (tree, diagnostics) = TurboTree::generate_with_diagnostics(parse).unwrap();
+        assert_eq!(
+            diagnostics
+                .iter()
+                .filter(|diagnostic| !matches!(diagnostic, Diagnostic::Parse { .. }))
+                .map(|diagnostic| {
+                    let (file, span) = diagnostic.location().unwrap();
+                    (diagnostic.to_string(), file, &input[span.range()])
+                })
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "figure label `fig:a` is used more than once".to_string(),
+                    None,
+                    "::: rust\nfn main() {}\n:::\n: Entry {#fig:a}\n"
+                ),
+                (
+                    "figure `fig:x` is referenced but never labeled".to_string(),
+                    None,
+                    "@fig:x"
+                )
+            ]
+        );
+        // and the two stray captions
+        assert_eq!(diagnostics.len(), 4);
+        assert_eq!(
+            tree.generate_html(None).unwrap(),
+            "<p>See <a class=\"reference\" href=\"#fig:b\">Figure 2</a>, \
+             <a class=\"reference\" href=\"#tbl:a\">Table 1</a> and @fig:x.</p>\n\
+             <figure id=\"fig:a\">\n<p><img src=\"a.png\" alt=\"A\"/></p>\n\
+             <figcaption>Figure 1: First</figcaption>\n</figure>\n\
+             <figure id=\"tbl:a\">\n<table>\n<thead>\n<tr><th>a</th></tr>\n</thead>\n\
+             <tbody>\n<tr><td>1</td></tr>\n</tbody>\n</table>\n\
+             <figcaption>Table 1</figcaption>\n</figure>\n\
+             <blockquote>\n<p><img src=\"b.png\" alt=\"B\"/> <img src=\"c.png\" alt=\"C\"/></p>\n\
+             <p>Two images</p>\n\
+             <figure id=\"fig:b\">\n<p><img src=\"b.png\" alt=\"B\"/></p>\n\
+             <figcaption>Figure 2: Second</figcaption>\n</figure>\n</blockquote>\n\
+             <figure>\n<pre><code class=\"language-rust\">\nfn main() {}\n</code></pre>\n\
+             <figcaption>Listing 1: Entry</figcaption>\n</figure>\n\
+             <p>stray</p>\n"
+        );
+    }
+}
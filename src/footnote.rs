@@ -0,0 +1,189 @@
+use crate::ast::{Footnote, TurboText, TurboTree};
+use crate::span::Span;
+use crate::visit::{fold_content, fold_tree_children, walk_text_mut, Fold, VisitorMut};
+use crate::Diagnostic;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Moves footnote definitions out of the content, numbers the references in
+/// order of their first appearance and appends the referenced footnotes.
+pub(crate) fn resolve(content: &mut Vec<TurboTree>, diagnostics: &mut Vec<Diagnostic>) {
+    let mut definitions = Definitions(vec![]);
+    *content = fold_content(&mut definitions, std::mem::take(content));
+
+    let mut defined: HashMap<String, (TurboText, Span)> = HashMap::new();
+    for (name, text, span) in definitions.0 {
+        match defined.entry(name) {
+            Entry::Occupied(entry) => diagnostics.push(Diagnostic::DuplicateFootnote {
+                name: entry.key().clone(),
+                file: None,
+                span,
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert((text, span));
+            }
+        }
+    }
+
+    let mut numbering = Numbering {
+        defined: &defined,
+        order: vec![],
+        references: HashMap::new(),
+        diagnostics,
+    };
+    content
+        .iter_mut()
+        .for_each(|node| numbering.visit_tree_mut(node));
+
+    let Numbering {
+        order,
+        references,
+        diagnostics,
+        ..
+    } = numbering;
+
+    let mut unused = defined
+        .iter()
+        .filter(|(name, _)| !references.contains_key(*name))
+        .map(|(name, (_, span))| (name.clone(), *span))
+        .collect::<Vec<_>>();
+    unused.sort_by_key(|(_, span)| (span.file, span.start));
+    diagnostics.extend(
+        unused
+            .into_iter()
+            .map(|(name, span)| Diagnostic::UnusedFootnote {
+                name,
+                file: None,
+                span,
+            }),
+    );
+
+    if order.is_empty() {
+        return;
+    }
+    let footnotes = order
+        .into_iter()
+        .map(|name| {
+            let (number, count) = references[&name];
+            let (text, span) = defined[&name].clone();
+            Footnote {
+                name,
+                number,
+                text,
+                references: count,
+                span,
+            }
+        })
+        .collect();
+    content.push(TurboTree::Footnotes {
+        footnotes,
+        span: Span::default(),
+    });
+}
+
+/// Removes footnote definitions from the content.
+struct Definitions(Vec<(String, TurboText, Span)>);
+
+impl Fold for Definitions {
+    fn fold_tree(&mut self, node: TurboTree) -> Vec<TurboTree> {
+        match node {
+            TurboTree::FootnoteDef { name, text, span } => {
+                self.0.push((name, text, span));
+                vec![]
+            }
+            node => vec![fold_tree_children(self, node)],
+        }
+    }
+}
+
+struct Numbering<'a> {
+    defined: &'a HashMap<String, (TurboText, Span)>,
+    order: Vec<String>,
+    /// footnote number and reference count per name
+    references: HashMap<String, (usize, usize)>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl VisitorMut for Numbering<'_> {
+    fn visit_text_mut(&mut self, text: &mut TurboText) {
+        let TurboText::FootnoteRef {
+            name,
+            number,
+            occurrence,
+            span,
+        } = text
+        else {
+            return walk_text_mut(self, text);
+        };
+        if !self.defined.contains_key(name) {
+            self.diagnostics.push(Diagnostic::UndefinedFootnote {
+                name: name.clone(),
+                file: None,
+                span: *span,
+            });
+            return;
+        }
+        let next = self.order.len() + 1;
+        let (assigned, count) = self
+            .references
+            .entry(name.clone())
+            .or_insert_with(|| (next, 0));
+        if *assigned == next {
+            self.order.push(name.clone());
+        }
+        *count += 1;
+        *number = Some(*assigned);
+        *occurrence = *count;
+    }
+}
+
+#[cfg(test)]
+mod footnote_tests {
+    use super::*;
+    use crate::parse_string;
+
+    #[test]
+    fn numbering_and_diagnostics() {
+        let input = "b[^b] a[^a] b[^b] c[^c]\n\n[^a]: A\n[^b]: B\n[^d]: D\n[^a]: A\n";
+        let (parse, _) = parse_string(input).unwrap();
+        let (tree, diagnostics) = TurboTree::generate_with_diagnostics(parse).unwrap();
+        let footnotes = match tree.get_vec().unwrap().last() {
+            Some(TurboTree::Footnotes { footnotes, .. }) => footnotes,
+            _ => panic!("missing footnotes"),
+        };
+        let numbers = footnotes
+            .iter()
+            .map(|footnote| (footnote.name.as_str(), footnote.number, footnote.references))
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, vec![("b", 1, 2), ("a", 2, 1)]);
+        let located = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let (file, span) = diagnostic.location().unwrap();
+                (diagnostic.to_string(), file, &input[span.range()])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            located,
+            vec![
+                (
+                    "footnote `a` is defined more than once".to_string(),
+                    None,
+                    "[^a]: A\n"
+                ),
+                (
+                    "footnote `c` is referenced but never defined".to_string(),
+                    None,
+                    "[^c]"
+                ),
+                (
+                    "footnote `d` is defined but never referenced".to_string(),
+                    None,
+                    "[^d]: D\n"
+                ),
+            ]
+        );
+        // the later definition is the duplicate
+        assert_eq!(diagnostics[0].location().unwrap().1.start.line, 6);
+    }
+}
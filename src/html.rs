@@ -1,5 +1,11 @@
-use crate::ast::TurboText;
-use crate::{Lang, ListKind, TurboTextMod, TurboTree};
+use crate::anchor::{plain_text, slug};
+use crate::ast::{Footnote, TableCell, TurboText};
+use crate::math::macro_definitions;
+use crate::render::Renderer;
+use crate::{
+    Alignment, Code, Error, FigureKind, Image, Lang, ListKind, StructureError, TocEntry,
+    TurboTextMod, TurboTree,
+};
 
 pub struct HtmlDefaults {
     pub title: String,
@@ -7,287 +13,713 @@ pub struct HtmlDefaults {
 }
 
 impl TurboTree {
-    pub fn generate_html(&self, defaults: Option<HtmlDefaults>) -> String {
-        let mut result = String::new();
-        match self {
-            TurboTree::Root { content } => {
-                if let Some(defaults) = &defaults {
-                    result.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
-                    result.push_str(&format!("<title>{}</title>\n", defaults.title));
-                    result.push_str(&defaults.default_html);
-                    result.push_str("</head>\n<body>\n");
-                }
-                content
-                    .iter()
-                    .for_each(|node| result.push_str(&node.to_html()));
+    /// Renders a `Root` to HTML, a full document if `defaults` are given.
+    pub fn generate_html(&self, defaults: Option<HtmlDefaults>) -> Result<String, Error> {
+        let mut renderer = HtmlRenderer::new(defaults);
+        renderer.render(self)?;
+        Ok(renderer.finish())
+    }
 
-                if let Some(_) = &defaults {
-                    result.push_str("</body>\n</html>\n<");
-                }
+    /// Renders a single node to HTML.
+    pub fn to_html(&self) -> Result<String, Error> {
+        let mut renderer = HtmlRenderer::new(None);
+        renderer.render_tree(self)?;
+        Ok(renderer.finish())
+    }
+}
 
+/// The HTML backend. To change how some nodes render, wrap it in a
+/// [`Renderer`] that handles those callbacks and returns it as its
+/// [`inner`](Renderer::inner) renderer for the others.
+#[derive(Default)]
+pub struct HtmlRenderer {
+    output: String,
+    defaults: Option<HtmlDefaults>,
+    /// whether a list item label is rendered, headings in labels are inline
+    in_label: bool,
+    permalinks: bool,
+    /// number of the equation figure being rendered, tagged on its math
+    equation: Option<usize>,
+}
+
+impl HtmlRenderer {
+    pub fn new(defaults: Option<HtmlDefaults>) -> Self {
+        Self {
+            defaults,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a link to its own anchor to the end of every heading.
+    pub fn with_permalinks(mut self, permalinks: bool) -> Self {
+        self.permalinks = permalinks;
+        self
+    }
+
+    /// The HTML rendered so far.
+    pub fn finish(self) -> String {
+        self.output
+    }
+
+    fn push(&mut self, html: &str) -> Result<(), Error> {
+        self.output.push_str(html);
+        Ok(())
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn document_start(&mut self) -> Result<(), Error> {
+        if let Some(defaults) = &self.defaults {
+            self.output.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+            self.output.push_str(&format!(
+                "<title>{}</title>\n",
+                escape_text(&defaults.title)
+            ));
+            self.output.push_str(&defaults.default_html);
+            self.output.push_str("</head>\n<body>\n");
+        }
+        Ok(())
+    }
+
+    fn document_end(&mut self) -> Result<(), Error> {
+        if self.defaults.is_some() {
+            self.output.push_str("</body>\n</html>\n");
+        }
+        Ok(())
+    }
+
+    fn paragraph_start(&mut self) -> Result<(), Error> {
+        self.push("<p>")
+    }
+
+    fn paragraph_end(&mut self) -> Result<(), Error> {
+        self.push("</p>\n")
+    }
+
+    fn heading_start(
+        &mut self,
+        size: usize,
+        id: &str,
+        number: Option<&str>,
+        text: &TurboText,
+    ) -> Result<(), Error> {
+        let id = heading_id(id, text);
+        self.push(&format!("<h{size} id=\"{id}\">"))?;
+        if let Some(number) = number {
+            self.push(&format!(
+                "<span class=\"number\">{}</span> ",
+                escape_text(number)
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn heading_end(&mut self, size: usize, id: &str, text: &TurboText) -> Result<(), Error> {
+        if self.permalinks {
+            let id = heading_id(id, text);
+            self.push(&format!(
+                " <a class=\"permalink\" href=\"#{id}\">&#182;</a>"
+            ))?;
+        }
+        self.push(&format!("</h{size}>"))?;
+        if self.in_label {
+            return Ok(());
+        }
+        self.push("\n")
+    }
+
+    fn list_start(&mut self, kind: &ListKind, start: isize) -> Result<(), Error> {
+        let tag = list_tag(kind, false, start)?;
+        self.push(&tag)?;
+        self.push("\n")
+    }
+
+    fn list_end(&mut self, kind: &ListKind, start: isize) -> Result<(), Error> {
+        let tag = list_tag(kind, true, start)?;
+        self.push(&tag)?;
+        self.push("\n")
+    }
+
+    fn list_item_start(
+        &mut self,
+        _id: usize,
+        marker: Option<&str>,
+        _check: Option<bool>,
+    ) -> Result<(), Error> {
+        if let Some(marker) = marker {
+            let marker = marker.replace('\\', "\\\\").replace('\'', "\\'");
+            self.push(&format!(
+                "<li style=\"list-style-type:{}\">\n",
+                escape_attribute(&format!("'{marker} '"))
+            ))?;
+        } else {
+            self.push("<li>\n")?;
+        }
+        Ok(())
+    }
+
+    fn list_item_end(&mut self) -> Result<(), Error> {
+        self.push("</li>\n")
+    }
+
+    fn label_start(
+        &mut self,
+        id: usize,
+        check: Option<bool>,
+        label: Option<&TurboTree>,
+    ) -> Result<(), Error> {
+        self.in_label = true;
+        match check {
+            Some(true) => self.push(&format!(
+                "<input type=\"checkbox\" id=\"checkbox{id}\" checked=\"checked\"/>"
+            ))?,
+            Some(false) => self.push(&format!("<input type=\"checkbox\" id=\"checkbox{id}\"/>"))?,
+            None => {}
+        }
+        if check.is_some() {
+            self.push(&format!("<label for=\"checkbox{id}\">"))
+        } else if let Some(TurboTree::Text { .. }) = label {
+            self.push("<p>")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn label_end(
+        &mut self,
+        _id: usize,
+        check: Option<bool>,
+        label: Option<&TurboTree>,
+    ) -> Result<(), Error> {
+        self.in_label = false;
+        if check.is_some() {
+            self.push("</label>\n")
+        } else if let Some(TurboTree::Text { .. }) = label {
+            self.push("</p>")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn code(&mut self, code: &Code) -> Result<(), Error> {
+        match &code.lang {
+            Lang::KaTeX => {
+                self.push("<div class=\"katex\">\n$$\n")?;
+                self.push(&escape_text(&code.code))?;
+                if let Some(number) = self.equation.take() {
+                    self.push(&format!("\\tag{{{number}}}\n"))?;
+                }
+                self.push("$$\n</div>\n")
             }
-            _ => panic!("must be root")
-        }
-        result
-    }
-    pub fn to_html(&self) -> String {
-        let mut result = String::new();
-        match self {
-            TurboTree::Root { .. } => { panic!("Shouldn't be callable here")},
-            TurboTree::Text(text) => {
-                result.push_str("<p>");
-                result.push_str(&text.to_html());
-                result.push_str("</p>\n")
-            }
-            TurboTree::Heading { size, text } => {
-                result.push_str(&format!("<h{}>", size));
-                result.push_str(&text.to_html());
-                result.push_str(&format!("</h{}>\n", size))
-            }
-            TurboTree::List { kind, items } => {
-                result.push_str(kind.to_html(false));
-                result.push_str("\n");
-                items
-                    .iter()
-                    .for_each(|node| result.push_str(&node.to_html()));
-                result.push_str(kind.to_html(true));
-                result.push_str("\n");
+            Lang::KaTeXMacros => self.push(&macros_script(&code.code)),
+            Lang::Mermaid => {
+                self.push("<div class=\"mermaid\">\n")?;
+                self.push(&escape_text(&code.code))?;
+                self.push("</div>\n")
             }
-            TurboTree::ListItem {
-                id,
-                check,
-                label,
-                items,
-            } => {
-                result.push_str("<li>\n");
-                if let Some(check) = check {
-                    if *check {
-                        result.push_str(&format!(
-                            "<input type=\"checkbox\" id=\"checkbox{id}\" checked=\"checked\"/>"
-                        ));
-                    } else {
-                        result.push_str(&format!("<input type=\"checkbox\" id=\"checkbox{id}\"/>"));
-                    }
-
-                    result.push_str(&format!("<label for=\"checkbox{id}\">"));
-                    if let Some(label) = label.as_ref() {
-                        match label {
-                            TurboTree::Text(text) => {
-                                result.push_str(&text.to_html());
-                            }
-                            TurboTree::Heading { size, text } => {
-                                result.push_str(&format!("<h{}>", size));
-                                result.push_str(&text.to_html());
-                                result.push_str(&format!("</h{}>", size))
-                            }
-                            _ => {
-                                panic!("Only Text and Heading should be a list label")
-                            }
-                        }
-                    }
-                    result.push_str("</label>\n")
-                } else {
-                    if let Some(label) = label.as_ref() {
-                        match label {
-                            TurboTree::Text(text) => {
-                                result.push_str("<p>");
-                                result.push_str(&text.to_html());
-                                result.push_str("</p>");
-                            }
-                            TurboTree::Heading { size, text } => {
-                                result.push_str(&format!("<h{}>", size));
-                                result.push_str(&text.to_html());
-                                result.push_str(&format!("</h{}>", size))
-                            }
-                            _ => {
-                                panic!("Only Text and Heading should be a list label")
-                            }
-                        }
-                    }
-                }
-                items
-                    .iter()
-                    .for_each(|node| result.push_str(&node.to_html()));
-                result.push_str("</li>\n")
+            Lang::Html => self.push(&code.code),
+            Lang::Other(lang) => {
+                self.push(&format!(
+                    "<pre><code class=\"language-{}\">\n",
+                    escape_attribute(lang)
+                ))?;
+                self.push(&escape_text(&code.code))?;
+                self.push("</code></pre>\n")
             }
-            TurboTree::Code(code) => {
-                match code.lang {
-                    Lang::KaTeX => {
-                        result.push_str("<div class=\"katex\">\n$$\n");
-                        result.push_str(&code.code);
-                        result.push_str("$$\n</div>\n");
-                    }
-                    Lang::Mermaid => {
-                        result.push_str("<div class=\"mermaid\">\n");
-                        result.push_str(&code.code);
-                        result.push_str("</div>\n");
-                    }
-                    Lang::Other(_) => {}
-                    _ => {
-                        result.push_str("<pre>");
-                        result.push_str(&format!(
-                            "<code class=\"{}\">\n",
-                            code.lang.as_str()
-                        ));
-                        result.push_str(&format!("{}", &code.code));
-                        result.push_str("</code></pre>\n");
-                    }
-                }
+            _ => {
+                self.push("<pre>")?;
+                self.push(&format!(
+                    "<code class=\"{}\">\n",
+                    escape_attribute(code.lang.as_str())
+                ))?;
+                self.push(&escape_text(&code.code))?;
+                self.push("</code></pre>\n")
             }
-            TurboTree::Horizontal => result.push_str("<hr/>\n"),
-            TurboTree::Empty => {}
-            TurboTree::Include(_) => {
-                panic!("Include should be substituted in HTML stage")
+        }
+    }
+
+    fn quote_start(&mut self) -> Result<(), Error> {
+        self.push("<blockquote>\n")
+    }
+
+    fn quote_end(&mut self) -> Result<(), Error> {
+        self.push("</blockquote>\n")
+    }
+
+    fn attribution_start(&mut self) -> Result<(), Error> {
+        self.push("<footer><cite>")
+    }
+
+    fn attribution_end(&mut self) -> Result<(), Error> {
+        self.push("</cite></footer>\n")
+    }
+
+    fn table_start(&mut self, _alignments: &[Alignment]) -> Result<(), Error> {
+        self.push("<table>\n")
+    }
+
+    fn table_end(&mut self) -> Result<(), Error> {
+        self.push("</table>\n")
+    }
+
+    fn table_section_start(&mut self, header: bool) -> Result<(), Error> {
+        self.push(if header { "<thead>\n" } else { "<tbody>\n" })
+    }
+
+    fn table_section_end(&mut self, header: bool) -> Result<(), Error> {
+        self.push(if header { "</thead>\n" } else { "</tbody>\n" })
+    }
+
+    fn table_row_start(&mut self) -> Result<(), Error> {
+        self.push("<tr>")
+    }
+
+    fn table_row_end(&mut self) -> Result<(), Error> {
+        self.push("</tr>\n")
+    }
+
+    fn table_cell_start(
+        &mut self,
+        cell: &TableCell,
+        alignment: Alignment,
+        header: bool,
+    ) -> Result<(), Error> {
+        let tag = if header { "th" } else { "td" };
+        self.push(&format!("<{tag}{}", alignment_style(alignment)))?;
+        if cell.colspan > 1 {
+            self.push(&format!(" colspan=\"{}\"", cell.colspan))?;
+        }
+        if cell.rowspan > 1 {
+            self.push(&format!(" rowspan=\"{}\"", cell.rowspan))?;
+        }
+        self.push(">")
+    }
+
+    fn table_cell_end(&mut self, _cell: &TableCell, header: bool) -> Result<(), Error> {
+        self.push(if header { "</th>" } else { "</td>" })
+    }
+
+    fn footnotes_start(&mut self) -> Result<(), Error> {
+        self.push("<section class=\"footnotes\">\n<hr/>\n<ol>\n")
+    }
+
+    fn footnotes_end(&mut self) -> Result<(), Error> {
+        self.push("</ol>\n</section>\n")
+    }
+
+    fn footnote_start(&mut self, footnote: &Footnote) -> Result<(), Error> {
+        let name = escape_attribute(&footnote.name);
+        self.push(&format!(
+            "<li id=\"fn-{name}\" value=\"{}\">",
+            footnote.number
+        ))
+    }
+
+    fn footnote_end(&mut self, footnote: &Footnote) -> Result<(), Error> {
+        let name = escape_attribute(&footnote.name);
+        for occurrence in 1..=footnote.references {
+            self.push(&format!(
+                " <a href=\"#fnref-{name}-{occurrence}\">&#8617;</a>"
+            ))?;
+        }
+        self.push("</li>\n")
+    }
+
+    fn horizontal(&mut self) -> Result<(), Error> {
+        self.push("<hr/>\n")
+    }
+
+    fn toc(&mut self, entries: &[TocEntry]) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.push("<nav class=\"toc\">\n")?;
+        self.push(&toc_list(entries))?;
+        self.push("</nav>\n")
+    }
+
+    fn figure_start(
+        &mut self,
+        kind: FigureKind,
+        id: Option<&str>,
+        number: Option<usize>,
+    ) -> Result<(), Error> {
+        let mut tag = String::from("<figure");
+        if kind == FigureKind::Equation {
+            // the number is tagged on the math, at the right margin
+            self.equation = number;
+            tag.push_str(" class=\"equation\"");
+        }
+        if let Some(id) = id {
+            tag.push_str(&format!(" id=\"{}\"", escape_attribute(id)));
+        }
+        tag.push_str(">\n");
+        self.push(&tag)
+    }
+
+    fn figure_end(
+        &mut self,
+        _kind: FigureKind,
+        _id: Option<&str>,
+        _number: Option<usize>,
+    ) -> Result<(), Error> {
+        self.equation = None;
+        self.push("</figure>\n")
+    }
+
+    fn caption_start(
+        &mut self,
+        kind: FigureKind,
+        number: Option<usize>,
+        caption: Option<&TurboText>,
+    ) -> Result<(), Error> {
+        if kind == FigureKind::Equation {
+            return match caption {
+                Some(_) => self.push("<figcaption>"),
+                None => Ok(()),
+            };
+        }
+        self.push("<figcaption>")?;
+        if let Some(number) = number {
+            self.push(&format!("{} {number}", kind.name()))?;
+            if caption.is_some() {
+                self.push(": ")?;
             }
         }
-        result
+        Ok(())
+    }
+
+    fn caption_end(
+        &mut self,
+        kind: FigureKind,
+        _number: Option<usize>,
+        caption: Option<&TurboText>,
+    ) -> Result<(), Error> {
+        if kind == FigureKind::Equation && caption.is_none() {
+            return Ok(());
+        }
+        self.push("</figcaption>\n")
+    }
+
+    fn modifier_start(&mut self, kind: TurboTextMod) -> Result<(), Error> {
+        self.push(modifier_tag(kind, false))
+    }
+
+    fn modifier_end(&mut self, kind: TurboTextMod) -> Result<(), Error> {
+        self.push(modifier_tag(kind, true))
+    }
+
+    fn link(&mut self, alias: Option<&str>, address: &str) -> Result<(), Error> {
+        self.push(&format!("<a href=\"{}\">", escape_attribute(address)))?;
+        self.push(&escape_text(alias.unwrap_or(address)))?;
+        self.push("</a>")
+    }
+
+    fn image(&mut self, image: &Image) -> Result<(), Error> {
+        self.push(&format!(
+            "<img src=\"{}\"",
+            escape_attribute(&image.address)
+        ))?;
+        if let Some(alt) = &image.alt {
+            self.push(&format!(" alt=\"{}\"", escape_attribute(alt)))?;
+        }
+        if let Some(title) = &image.title {
+            self.push(&format!(" title=\"{}\"", escape_attribute(title)))?;
+        }
+        if let Some(width) = &image.width {
+            self.push(&format!(" width=\"{}\"", escape_attribute(width)))?;
+        }
+        if let Some(height) = &image.height {
+            self.push(&format!(" height=\"{}\"", escape_attribute(height)))?;
+        }
+        self.push("/>")
+    }
+
+    fn footnote_ref(
+        &mut self,
+        name: &str,
+        number: Option<usize>,
+        occurrence: usize,
+    ) -> Result<(), Error> {
+        if let Some(number) = number {
+            let name = escape_attribute(name);
+            self.push(&format!(
+                "<sup class=\"footnote-ref\"><a href=\"#fn-{name}\" id=\"fnref-{name}-{occurrence}\">{number}</a></sup>"
+            ))
+        } else {
+            self.push(&format!("[^{}]", escape_text(name)))
+        }
+    }
+
+    fn figure_ref(&mut self, id: &str, number: Option<(FigureKind, usize)>) -> Result<(), Error> {
+        if let Some((kind, number)) = number {
+            self.push(&format!(
+                "<a class=\"reference\" href=\"#{}\">{} {number}</a>",
+                escape_attribute(id),
+                kind.name()
+            ))
+        } else {
+            self.push(&format!("@{}", escape_text(id)))
+        }
+    }
+
+    fn plain(&mut self, text: &str) -> Result<(), Error> {
+        self.push(&escape_text(text))
+    }
+
+    fn math(&mut self, math: &str) -> Result<(), Error> {
+        self.push("\\(")?;
+        self.push(&escape_text(math))?;
+        self.push("\\)")
+    }
+
+    fn raw_html(&mut self, html: &str) -> Result<(), Error> {
+        self.push(html)
+    }
+
+    fn line_break(&mut self) -> Result<(), Error> {
+        self.push("<br/>")
     }
 }
 
-impl TurboText {
-    pub fn to_html(&self) -> String {
-        let mut result = String::new();
-        match self {
-            TurboText::TextContainer(text) => {
-                text.iter()
-                    .for_each(|node| result.push_str(&node.to_html()));
-            }
-            TurboText::Mod { kind, text } => {
-                result.push_str(kind.to_html(false));
-                text.iter()
-                    .for_each(|node| result.push_str(&node.to_html()));
-                result.push_str(kind.to_html(true));
-            }
-            TurboText::Link { alias, address } => {
-                result.push_str(&format!("<a href=\"{}\">", address));
-                if let Some(alias) = alias {
-                    result.push_str(alias);
-                } else {
-                    result.push_str(address);
-                }
-                result.push_str("</a>");
-            }
-            TurboText::Plain(text) => {
-                result.push_str(text);
-            }
-            TurboText::NewLine => result.push_str("<br/>"),
+/// Adds the macros to `window.katexMacros`, which the default template passes
+/// to KaTeX. They apply to all math of the page, wherever they are defined.
+fn macros_script(code: &str) -> String {
+    let macros = macro_definitions(code)
+        .map(|(name, expansion)| format!("{}: {}", json_string(name), json_string(expansion)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "<script>window.katexMacros = Object.assign(window.katexMacros || {{}}, {{{macros}}});</script>\n"
+    )
+}
+
+/// A JSON string literal that can't end the script it is in.
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '<' => result.push_str("\\u003c"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
         }
-        result
     }
+    result.push('"');
+    result
 }
 
-impl TurboTextMod {
-    pub fn to_html(&self, close: bool) -> &'static str {
-        return match self {
-            TurboTextMod::Bold => {
-                if !close {
-                    "<b>"
-                } else {
-                    "</b>"
-                }
+/// Nested lists of links to the headings, with their text without formatting.
+fn toc_list(entries: &[TocEntry]) -> String {
+    let mut result = String::from("<ul>\n");
+    for entry in entries {
+        let text = match &entry.number {
+            Some(number) => format!("{number} {}", plain_text(&entry.text)),
+            None => plain_text(&entry.text),
+        };
+        result.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape_attribute(&entry.id),
+            escape_text(&text)
+        ));
+        if !entry.children.is_empty() {
+            result.push('\n');
+            result.push_str(&toc_list(&entry.children));
+        }
+        result.push_str("</li>\n");
+    }
+    result.push_str("</ul>\n");
+    result
+}
+
+/// The escaped id of a heading, the slug of its text in trees built by hand
+/// without ids.
+fn heading_id(id: &str, text: &TurboText) -> String {
+    if id.is_empty() {
+        escape_attribute(&slug(text))
+    } else {
+        escape_attribute(id)
+    }
+}
+
+/// Escapes text content, quotes only need escaping in attributes.
+pub fn escape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escapes an attribute value, for attributes quoted with `"` or `'`.
+pub fn escape_attribute(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn modifier_tag(kind: TurboTextMod, close: bool) -> &'static str {
+    match kind {
+        TurboTextMod::Bold => {
+            if !close {
+                "<b>"
+            } else {
+                "</b>"
             }
-            TurboTextMod::Cursive => {
-                if !close {
-                    "<i>"
-                } else {
-                    "</i>"
-                }
+        }
+        TurboTextMod::Cursive => {
+            if !close {
+                "<i>"
+            } else {
+                "</i>"
             }
-            TurboTextMod::Strike => {
-                if !close {
-                    "<del>"
-                } else {
-                    "</del>"
-                }
+        }
+        TurboTextMod::Strike => {
+            if !close {
+                "<del>"
+            } else {
+                "</del>"
             }
-            TurboTextMod::Underline => {
-                if !close {
-                    "<ins>"
-                } else {
-                    "</ins>"
-                }
+        }
+        TurboTextMod::Underline => {
+            if !close {
+                "<ins>"
+            } else {
+                "</ins>"
             }
-            TurboTextMod::Code => {
-                if !close {
-                    "<code>"
-                } else {
-                    "</code>"
-                }
+        }
+        TurboTextMod::Code => {
+            if !close {
+                "<code>"
+            } else {
+                "</code>"
             }
-            TurboTextMod::Sup => {
-                if !close {
-                    "<sup>"
-                } else {
-                    "</sup>"
-                }
+        }
+        TurboTextMod::Sup => {
+            if !close {
+                "<sup>"
+            } else {
+                "</sup>"
             }
-            TurboTextMod::Sub => {
-                if !close {
-                    "<sub>"
-                } else {
-                    "</sub>"
-                }
+        }
+        TurboTextMod::Sub => {
+            if !close {
+                "<sub>"
+            } else {
+                "</sub>"
             }
-        };
+        }
     }
 }
 
-impl ListKind {
-    pub fn to_html(&self, close: bool) -> &'static str {
-        return match self {
-            ListKind::Numbered => {
-                if !close {
-                    "<ol type=\"1\">"
-                } else {
-                    "</ol>"
-                }
-            }
-            ListKind::AlphabetUpper => {
-                if !close {
-                    "<ol type=\"1\">"
-                } else {
-                    "</ol>"
-                }
-            }
-            ListKind::AlphabetLower => {
-                if !close {
-                    "<ol type=\"1\">"
-                } else {
-                    "</ol>"
-                }
-            }
-            ListKind::RomanUpper => {
-                if !close {
-                    "<ol type=\"1\">"
-                } else {
-                    "</ol>"
-                }
-            }
-            ListKind::RomanLower => {
-                if !close {
-                    "<ol type=\"1\">"
-                } else {
-                    "</ol>"
-                }
-            }
-            ListKind::Unordered(ty) => {
-                if close {
-                    return "</ul>";
-                }
-                if ty.is_none() {
-                    return "<ul>";
-                }
-                let ty = ty.unwrap();
-                return match ty {
-                    0 => "<ul style=\"list-style-type:none\">",
-                    1 => "<ul style=\"list-style-type:circle\">",
-                    2 => "<ul style=\"list-style-type:disc\">",
-                    3 => "<ul style=\"list-style-type:square\">",
-                    _ => {
-                        panic!("unsupported type")
-                    }
-                };
+fn alignment_style(alignment: Alignment) -> &'static str {
+    match alignment {
+        Alignment::Default => "",
+        Alignment::Left => " style=\"text-align:left\"",
+        Alignment::Center => " style=\"text-align:center\"",
+        Alignment::Right => " style=\"text-align:right\"",
+    }
+}
+
+fn list_tag(kind: &ListKind, close: bool, start: isize) -> Result<String, Error> {
+    let ty = match kind {
+        ListKind::Numbered => "1",
+        ListKind::AlphabetUpper => "A",
+        ListKind::AlphabetLower => "a",
+        ListKind::RomanUpper => "I",
+        ListKind::RomanLower => "i",
+        ListKind::Custom => {
+            return Ok(if close { "</ul>" } else { "<ul>" }.to_string());
+        }
+        ListKind::Unordered(ty) => {
+            if close {
+                return Ok("</ul>".to_string());
             }
+            let Some(ty) = ty else {
+                return Ok("<ul>".to_string());
+            };
+            return match ty {
+                0 => Ok("<ul style=\"list-style-type:none\">".to_string()),
+                1 => Ok("<ul style=\"list-style-type:circle\">".to_string()),
+                2 => Ok("<ul style=\"list-style-type:disc\">".to_string()),
+                3 => Ok("<ul style=\"list-style-type:square\">".to_string()),
+                _ => Err(StructureError::UnknownListStyle(*ty).into()),
+            };
+        }
+    };
+    Ok(if close {
+        "</ol>".to_string()
+    } else if start == 1 {
+        format!("<ol type=\"{ty}\">")
+    } else {
+        format!("<ol type=\"{ty}\" start=\"{start}\">")
+    })
+}
+
+#[cfg(test)]
+mod html_tests {
+    use crate::{parse_string, HtmlDefaults, TurboTree};
+
+    fn html(input: &str) -> String {
+        let (parse, _) = parse_string(input).unwrap();
+        TurboTree::generate(parse)
+            .unwrap()
+            .generate_html(None)
+            .unwrap()
+    }
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        assert_eq!(
+            html("a <b> & $x<y$ [<i>](a\"b.html)\n"),
+            "<p>a &lt;b&gt; &amp; \\(x&lt;y\\) <a href=\"a&quot;b.html\">&lt;i&gt;</a></p>\n"
+        );
+        assert_eq!(
+            html("::: rust\nlet v: Vec<&str> = vec![];\n:::\n"),
+            "<pre><code class=\"language-rust\">\nlet v: Vec&lt;&amp;str&gt; = vec![];\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn raw_html() {
+        assert_eq!(
+            html("::: html\n<div class=\"x\"></div>\n:::\na \\html{<kbd>Ctrl</kbd>} \\{*b*}\n"),
+            "<div class=\"x\"></div>\n<p>a <kbd>Ctrl</kbd> *b*</p>\n"
+        );
+        // unknown languages are kept as code
+        assert_eq!(
+            html("::: zig\nconst x = 1;\n:::\n"),
+            "<pre><code class=\"language-zig\">\nconst x = 1;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn document() {
+        let (parse, _) = parse_string("text\n").unwrap();
+        let defaults = HtmlDefaults {
+            title: "<title>".to_string(),
+            default_html: String::new(),
         };
+        let html = TurboTree::generate(parse)
+            .unwrap()
+            .generate_html(Some(defaults))
+            .unwrap();
+        assert!(html.contains("<title>&lt;title&gt;</title>"));
+        assert!(html.ends_with("</body>\n</html>\n"));
     }
 }
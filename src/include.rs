@@ -0,0 +1,275 @@
+use crate::ast::TurboText;
+use crate::ast::{generate_content, Context, TurboTree};
+use crate::link::Links;
+use crate::span::Span;
+use crate::visit::{
+    fold_content, fold_tree_children, walk_text_mut, walk_tree_mut, Fold, VisitorMut,
+};
+use crate::{parse_with_options, Diagnostic, Error, Options, StructureError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Generates files with all their includes spliced in place.
+pub(crate) struct Resolver {
+    /// files currently being included, to detect cycles
+    stack: Vec<PathBuf>,
+    context: Context,
+    options: Options,
+    /// directory includes have to stay in, set by the safe mode
+    root: Option<PathBuf>,
+    links: Option<Links>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    /// every file read, the spans of its content point into it by index
+    pub(crate) files: Vec<PathBuf>,
+    /// bytes read so far, counting files again each time they are included
+    read: usize,
+}
+
+impl Resolver {
+    pub(crate) fn new(check_links: bool, options: &Options) -> Self {
+        Self {
+            stack: vec![],
+            context: Context::new(options),
+            options: options.clone(),
+            root: None,
+            links: check_links.then(|| Links::new(options)),
+            diagnostics: vec![],
+            files: vec![],
+            read: 0,
+        }
+    }
+
+    /// Keeps the includes in `root`, the root of the document that links here.
+    pub(crate) fn within(mut self, root: Option<&Path>) -> Self {
+        self.root = root.map(Path::to_path_buf);
+        self
+    }
+
+    /// Generates the content of the file at `path` and splices the content of
+    /// every included file in place of its `Include`. `prefix` is the directory
+    /// of the file relative to the including document. Also returns the span of
+    /// the whole file.
+    pub(crate) fn generate_file(
+        &mut self,
+        path: &Path,
+        from: Option<&Path>,
+        prefix: &Path,
+    ) -> Result<(Vec<TurboTree>, Span), Error> {
+        // `notes.v1` is `notes.v1.tmd`, other suffixes are part of the name
+        let path = if path.extension().is_some_and(|ext| ext == "tmd") {
+            path.to_path_buf()
+        } else {
+            let mut path = path.as_os_str().to_owned();
+            path.push(".tmd");
+            PathBuf::from(path)
+        };
+        let io_error = |error| Error::Io {
+            path: path.clone(),
+            from: from.map(Path::to_path_buf),
+            error,
+        };
+        let canonical = fs::canonicalize(&path).map_err(io_error)?;
+        if let Some(safe) = &self.options.safe {
+            let root = match (&self.root, &safe.include_root) {
+                (Some(root), _) => root.clone(),
+                (None, Some(root)) => fs::canonicalize(root).map_err(|error| Error::Io {
+                    path: root.clone(),
+                    from: None,
+                    error,
+                })?,
+                (None, None) => canonical.parent().unwrap_or(Path::new("")).to_path_buf(),
+            };
+            if !canonical.starts_with(&root) {
+                return Err(Error::IncludeOutsideRoot { path, root });
+            }
+            self.root = Some(root);
+        }
+        if self.stack.contains(&canonical) {
+            let mut cycle = self.stack.clone();
+            cycle.push(canonical);
+            return Err(Error::IncludeCycle(cycle));
+        }
+        let mut content = fs::read_to_string(&path).map_err(io_error)?;
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        // a file included twice by each of a few others adds up quickly
+        self.read += content.len();
+        if let Some(safe) = &self.options.safe {
+            if self.read > safe.max_input {
+                return Err(Error::InputTooLarge {
+                    file: Some(self.files.first().unwrap_or(&path).clone()),
+                    size: self.read,
+                    max: safe.max_input,
+                });
+            }
+        }
+
+        let (parse, errors) =
+            parse_with_options(&content, &self.options).map_err(|error| error.in_file(&path))?;
+        self.diagnostics
+            .extend(errors.into_iter().map(|error| Diagnostic::Parse {
+                file: Some(path.clone()),
+                error,
+            }));
+        let root = parse.root().ok_or(StructureError::NotRoot)?;
+        let mut tree =
+            generate_content(root, &mut self.context).map_err(|error| error.in_file(&path))?;
+        let mut tag = Tag(self.files.len());
+        tree.iter_mut().for_each(|node| tag.visit_tree_mut(node));
+        self.files.push(path.clone());
+        let errors = self.context.take_errors(Some(&path))?;
+        self.diagnostics.extend(errors);
+        if let Some(links) = &mut self.links {
+            links.rewrite(
+                &mut tree,
+                &path,
+                self.root.as_deref(),
+                prefix,
+                &mut self.diagnostics,
+            );
+        }
+
+        self.stack.push(canonical);
+        let mut splice = Splice {
+            resolver: self,
+            from: &path,
+            prefix,
+            error: None,
+        };
+        let tree = fold_content(&mut splice, tree);
+        if let Some(error) = splice.error {
+            return Err(error);
+        }
+        self.stack.pop();
+
+        Ok((tree, parse.span()))
+    }
+}
+
+/// Sets the file index of every span.
+struct Tag(usize);
+
+impl VisitorMut for Tag {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        node.span_mut().file = self.0;
+        walk_tree_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, text: &mut TurboText) {
+        text.span_mut().file = self.0;
+        walk_text_mut(self, text)
+    }
+}
+
+/// Replaces includes with the content of the included file, stops at the
+/// first error.
+struct Splice<'a> {
+    resolver: &'a mut Resolver,
+    /// the including file
+    from: &'a Path,
+    prefix: &'a Path,
+    error: Option<Error>,
+}
+
+impl Fold for Splice<'_> {
+    fn fold_tree(&mut self, node: TurboTree) -> Vec<TurboTree> {
+        if self.error.is_some() {
+            return vec![node];
+        }
+        let TurboTree::Include { path, .. } = &node else {
+            return vec![fold_tree_children(self, node)];
+        };
+        let base = self.from.parent().unwrap_or(Path::new(""));
+        let included_prefix = self.prefix.join(path);
+        let included_prefix = included_prefix.parent().unwrap_or(Path::new(""));
+        match self
+            .resolver
+            .generate_file(&base.join(path), Some(self.from), included_prefix)
+        {
+            Ok((included, _)) => included,
+            Err(error) => {
+                self.error = Some(error);
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod include_tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::Diagnostic;
+
+    #[test]
+    fn splices_relative_includes() {
+        let dir = TempDir::new(
+            "include-splice",
+            &[
+                ("main.tmd", "a\n- item\n  @[sub/part]\n"),
+                ("sub/part.tmd", "b[^n]\n@[leaf.tmd]\n"),
+                ("sub/leaf.tmd", "[^n]: note\n"),
+            ],
+        );
+        let (tree, diagnostics) = TurboTree::generate_file(dir.join("main")).unwrap();
+        assert_eq!(diagnostics, Vec::<Diagnostic>::new());
+        assert_eq!(
+            tree.generate_html(None).unwrap(),
+            "<p>a</p>\n<ul>\n<li>\n<p>item</p><p>b<sup class=\"footnote-ref\"><a href=\"#fn-n\" id=\"fnref-n-1\">1</a></sup></p>\n</li>\n</ul>\n\
+             <section class=\"footnotes\">\n<hr/>\n<ol>\n<li id=\"fn-n\" value=\"1\">note <a href=\"#fnref-n-1\">&#8617;</a></li>\n</ol>\n</section>\n"
+        );
+    }
+
+    #[test]
+    fn locates_diagnostics_in_included_files() {
+        let part = "b[^y]\n\n[^x]: X\n";
+        let dir = TempDir::new(
+            "include-locate",
+            &[("main.tmd", "a[^x]\n@[sub/part]\n"), ("sub/part.tmd", part)],
+        );
+        let (_, diagnostics) = TurboTree::generate_file(dir.join("main.tmd")).unwrap();
+        let (file, span) = diagnostics[0].location().unwrap();
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "footnote `y` is referenced but never defined"
+        );
+        assert_eq!(file, Some(dir.join("sub/part.tmd").as_path()));
+        assert_eq!(&part[span.range()], "[^y]");
+    }
+
+    #[test]
+    fn keeps_dotted_names() {
+        let dir = TempDir::new(
+            "include-dotted",
+            &[
+                ("main.tmd", "@[notes.v1]\n"),
+                ("notes.v1.tmd", "v1\n"),
+                ("notes.tmd", "plain\n"),
+            ],
+        );
+        let (tree, _) = TurboTree::generate_file(dir.join("main")).unwrap();
+        assert_eq!(tree.generate_html(None).unwrap(), "<p>v1</p>\n");
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let dir = TempDir::new("include-cycle", &[("a.tmd", "@[b]\n"), ("b.tmd", "@[a]\n")]);
+        match TurboTree::generate_file(dir.join("a.tmd")) {
+            Err(Error::IncludeCycle(chain)) => assert_eq!(chain.len(), 3),
+            other => panic!("expected cycle, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let dir = TempDir::new("include-missing", &[("main.tmd", "@[nope]\n")]);
+        match TurboTree::generate_file(dir.join("main.tmd")) {
+            Err(Error::Io { path, from, .. }) => {
+                assert_eq!(path, dir.join("nope.tmd"));
+                assert_eq!(from, Some(dir.join("main.tmd")));
+            }
+            other => panic!("expected io error, got {other:?}"),
+        }
+    }
+}
@@ -1,15 +1,41 @@
-use crate::parser::{parser, Turbo};
-use chumsky::{error::Simple, Parser};
+use crate::parser::{limited_parser, Turbo};
+use chumsky::Parser;
 use std::fs;
 
+mod anchor;
 mod ast;
+mod code;
+mod diagnostic;
+mod error;
+mod figure;
+mod footnote;
 mod html;
+mod include;
+mod link;
+mod math;
+mod numbering;
 mod parser;
+mod render;
+mod safe;
+mod span;
+#[cfg(test)]
+mod testing;
+mod toc;
+pub mod visit;
 
 use std::fmt;
 
-pub use ast::TurboTree;
-pub use html::HtmlDefaults;
+pub use ast::{Footnote, ModifierPolicy, Options, TableCell, TurboText, TurboTree};
+pub use code::{CodeHandlers, CodeOutput};
+pub use diagnostic::{Diagnostic, ParseError, ParseErrorKind, Severity};
+pub use error::{Error, StructureError};
+pub use figure::FigureKind;
+pub use html::{escape_attribute, escape_text, HtmlDefaults, HtmlRenderer};
+pub use numbering::{dotted, SectionNumbering};
+pub use render::Renderer;
+pub use safe::SafeMode;
+pub use span::{Position, Span};
+pub use toc::{TocEntry, TocOptions};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum TurboTextMod {
@@ -22,6 +48,21 @@ pub enum TurboTextMod {
     Sub,
 }
 
+impl TurboTextMod {
+    /// The flag that opens and closes the modifier.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TurboTextMod::Bold => "*",
+            TurboTextMod::Cursive => "**",
+            TurboTextMod::Strike => "~",
+            TurboTextMod::Underline => "__",
+            TurboTextMod::Code => "`",
+            TurboTextMod::Sup => "^",
+            TurboTextMod::Sub => "_",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ListKind {
     Numbered,
@@ -30,17 +71,33 @@ pub enum ListKind {
     RomanUpper,
     RomanLower,
     Unordered(Option<usize>),
+    Custom,
+}
+
+impl ListKind {
+    /// Whether an item of kind `next` continues a list of this kind.
+    /// Items without a style or with a custom marker continue any unordered list.
+    pub fn continued_by(&self, next: &ListKind) -> bool {
+        match (self, next) {
+            (
+                ListKind::Unordered(_) | ListKind::Custom,
+                ListKind::Unordered(None) | ListKind::Custom,
+            ) => true,
+            _ => self == next,
+        }
+    }
 }
 
 impl fmt::Display for ListKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
-            ListKind::Numbered => "Unordered".to_string(),
+            ListKind::Numbered => "Numbered".to_string(),
             ListKind::AlphabetUpper => "Alphabet Upper".to_string(),
             ListKind::AlphabetLower => "Alphabet Lower".to_string(),
             ListKind::RomanUpper => "Roman Upper".to_string(),
             ListKind::RomanLower => "Roman Lower".to_string(),
             ListKind::Unordered(_) => "Unordered".to_string(),
+            ListKind::Custom => "Custom".to_string(),
         };
         write!(f, "{}", s)
     }
@@ -52,64 +109,118 @@ pub struct Code {
     pub code: String,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Alignment {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image {
+    pub alt: Option<String>,
+    pub address: String,
+    pub title: Option<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Lang {
     Turbo,
     KaTeX,
+    /// Macros for all math of the document, one `\name: expansion` per line.
+    KaTeXMacros,
     Mermaid,
     Rust,
     Nim,
     Python,
     C,
     CPP,
+    Table,
+    /// Raw HTML, other formats leave it out.
+    Html,
     Other(String),
 }
 
-pub fn parse_file(path: &str) -> Turbo {
+pub fn parse_file(path: &str) -> Result<(Turbo, Vec<ParseError>), Error> {
     let path = if path.ends_with(".tmd") {
         path.to_string()
     } else {
         format!("{}.tmd", path)
     };
-    let mut content = match fs::read_to_string(&path) {
-        Ok(content) => content,
-        Err(e) => panic!("{e}"),
-    };
+    let mut content = fs::read_to_string(&path).map_err(|error| Error::Io {
+        path: path.clone().into(),
+        from: None,
+        error,
+    })?;
     if !content.ends_with("\n") {
         content.push('\n')
     }
 
-    let (turbo, _errors) = parse(&content);
-
-    turbo
+    parse(&content).map_err(|error| error.in_file(path.as_ref()))
 }
 
-pub fn parse_string(content: &str) -> Turbo {
+pub fn parse_string(content: &str) -> Result<(Turbo, Vec<ParseError>), Error> {
     let content = if !content.ends_with("\n") {
         let mut content = content.to_string();
-        content.push_str("\n");
+        content.push('\n');
         content
     } else {
         content.to_string()
     };
 
-    let (turbo, _errors) = parse(&content);
+    parse(&content)
+}
 
-    turbo
+/// Parses `content`, problems are reported in the errors and parsing carries on
+/// with the next line. Fails only if the parser can't recover at all.
+pub fn parse(content: &str) -> Result<(Turbo, Vec<ParseError>), Error> {
+    parse_with_options(content, &Options::default())
 }
 
-pub fn parse(content: &str) -> (Turbo, Vec<Simple<char>>) {
-    let (turbo, errors) = parser().parse_recovery(content);
-    (turbo.unwrap(), errors)
+/// Like [`parse`], also failing if `content` exceeds the limits of the safe mode.
+pub fn parse_with_options(
+    content: &str,
+    options: &Options,
+) -> Result<(Turbo, Vec<ParseError>), Error> {
+    if let Some(safe) = &options.safe {
+        if content.len() > safe.max_input {
+            return Err(Error::InputTooLarge {
+                file: None,
+                size: content.len(),
+                max: safe.max_input,
+            });
+        }
+    }
+    let max_depth = options.max_depth();
+    let (turbo, errors) = limited_parser(max_depth).parse_recovery(span::stream(content));
+    if let Some(error) = errors
+        .iter()
+        .find(|error| error.kind == ParseErrorKind::NestingTooDeep)
+    {
+        return Err(Error::NestingTooDeep {
+            file: None,
+            span: error.span,
+            max: max_depth,
+        });
+    }
+    match turbo {
+        Some(turbo) => Ok((turbo, errors)),
+        None => Err(Error::Parse { file: None, errors }),
+    }
 }
 
 impl From<&str> for Lang {
     fn from(value: &str) -> Self {
         use Lang::*;
-        let res = match value {
+
+        match value {
             "turbo" => Turbo,
             "katex" => KaTeX,
             "math" => KaTeX,
+            "katex-macros" => KaTeXMacros,
             "mermaid" => Mermaid,
             "rust" => Rust,
             "nim" => Nim,
@@ -117,33 +228,38 @@ impl From<&str> for Lang {
             "c" => C,
             "cpp" => CPP,
             "c++" => CPP,
+            "table" => Table,
+            "html" => Html,
             _ => Other(value.to_string()),
-        };
-        res
+        }
     }
 }
 
 impl Lang {
     pub fn as_str(&self) -> &str {
-        return match self {
+        match self {
             Lang::Turbo => "turbo",
             Lang::KaTeX => "katex",
+            Lang::KaTeXMacros => "katex-macros",
             Lang::Rust => "language-rust",
             Lang::Nim => "language-nim",
             Lang::Python => "language-python",
             Lang::Mermaid => "mermaid",
             Lang::C => "language-c",
             Lang::CPP => "language-cpp",
+            Lang::Table => "table",
+            Lang::Html => "html",
             Lang::Other(s) => s.as_str(),
-        };
+        }
     }
 }
 
 impl Turbo {
-    pub fn root(&self) -> &Vec<Turbo> {
+    /// The content of a `Root`, `None` for any other node.
+    pub fn root(&self) -> Option<&Vec<Turbo>> {
         match self {
-            Turbo::Root(vec) => vec,
-            _ => panic!("illegal call"),
+            Turbo::Root { content, .. } => Some(content),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,335 @@
+use crate::anchor::{assign_ids, heading_anchors};
+use crate::ast::{TurboText, TurboTree};
+use crate::figure;
+use crate::include::Resolver;
+use crate::span::Span;
+use crate::visit::{walk_text_mut, VisitorMut};
+use crate::{Diagnostic, Options};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Rewrites links to `.tmd` documents to their generated `.html` and checks
+/// that the target and its `#fragment` exist.
+pub(crate) struct Links {
+    /// the options of the linking document, targets are generated with them
+    options: Options,
+    targets: HashMap<PathBuf, Target>,
+}
+
+/// What a link target turned out to be.
+enum Target {
+    /// the ids of its headings and figures
+    Anchors(HashSet<String>),
+    Missing,
+    /// the target can't be generated, for the given reason
+    Unchecked(String),
+}
+
+impl Links {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            options: options.clone(),
+            targets: HashMap::new(),
+        }
+    }
+
+    /// `file` is the document containing the links, `prefix` its directory
+    /// relative to the generated document. In the safe mode `root` is the
+    /// include root, targets outside of it are not read.
+    pub(crate) fn rewrite(
+        &mut self,
+        content: &mut [TurboTree],
+        file: &Path,
+        root: Option<&Path>,
+        prefix: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        // an unknown directory puts every target outside
+        let canonical_dir = fs::canonicalize(file)
+            .ok()
+            .and_then(|file| file.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        let mut rewrite = Rewrite {
+            links: self,
+            file,
+            dir: file.parent().unwrap_or(Path::new("")),
+            root: root.map(|root| (root, canonical_dir.as_path())),
+            prefix,
+            diagnostics,
+        };
+        content
+            .iter_mut()
+            .for_each(|node| rewrite.visit_tree_mut(node));
+    }
+
+    fn target(&mut self, target: &Path, root: Option<&Path>) -> &Target {
+        let options = &self.options;
+        self.targets.entry(target.to_path_buf()).or_insert_with(|| {
+            if !target.is_file() {
+                return Target::Missing;
+            }
+            // links of the target are not checked, so documents may link each other
+            let mut resolver = Resolver::new(false, options).within(root);
+            match resolver.generate_file(target, None, Path::new("")) {
+                Ok((mut content, _)) => {
+                    assign_ids(&mut content, &mut vec![]);
+                    let mut anchors = heading_anchors(&content);
+                    anchors.extend(figure::ids(&content));
+                    Target::Anchors(anchors)
+                }
+                Err(error) => Target::Unchecked(error.to_string()),
+            }
+        })
+    }
+}
+
+struct Rewrite<'a> {
+    links: &'a mut Links,
+    file: &'a Path,
+    dir: &'a Path,
+    /// the include root and the canonical `dir` in the safe mode
+    root: Option<(&'a Path, &'a Path)>,
+    prefix: &'a Path,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Rewrite<'_> {
+    fn rewrite_address(&mut self, address: &str, span: Span) -> Option<String> {
+        if address.contains("://") || address.starts_with('/') || address.starts_with("mailto:") {
+            return None;
+        }
+        let (target, fragment) = match address.split_once('#') {
+            Some((target, fragment)) => (target, Some(fragment)),
+            None => (address, None),
+        };
+        if !target.ends_with(".tmd") {
+            return None;
+        }
+
+        // decided before touching the file system, so the diagnostics don't
+        // tell which files exist outside of the root
+        let outside = self.root.is_some_and(|(root, canonical_dir)| {
+            !canonical_dir.is_absolute() || !lexical(&canonical_dir.join(target)).starts_with(root)
+        });
+        let unchecked = Target::Unchecked("it is outside of the include root".to_string());
+        let checked = if outside {
+            &unchecked
+        } else {
+            self.links
+                .target(&self.dir.join(target), self.root.map(|(root, _)| root))
+        };
+        match checked {
+            Target::Missing => self.diagnostics.push(Diagnostic::MissingLinkTarget {
+                file: self.file.to_path_buf(),
+                address: address.to_string(),
+                span,
+            }),
+            Target::Unchecked(reason) => self.diagnostics.push(Diagnostic::UncheckedLinkTarget {
+                file: self.file.to_path_buf(),
+                address: address.to_string(),
+                span,
+                reason: reason.clone(),
+            }),
+            Target::Anchors(anchors) => {
+                if let Some(fragment) = fragment {
+                    if !anchors.contains(fragment) {
+                        self.diagnostics.push(Diagnostic::MissingLinkAnchor {
+                            file: self.file.to_path_buf(),
+                            address: address.to_string(),
+                            span,
+                        })
+                    }
+                }
+            }
+        }
+
+        let mut rewritten = normalize(&self.prefix.join(target).with_extension("html"));
+        if let Some(fragment) = fragment {
+            rewritten.push('#');
+            rewritten.push_str(fragment);
+        }
+        Some(rewritten)
+    }
+}
+
+impl VisitorMut for Rewrite<'_> {
+    fn visit_text_mut(&mut self, text: &mut TurboText) {
+        let TurboText::Link {
+            alias,
+            address,
+            span,
+        } = text
+        else {
+            return walk_text_mut(self, text);
+        };
+        if let Some(rewritten) = self.rewrite_address(address, *span) {
+            alias.get_or_insert_with(|| address.clone());
+            *address = rewritten;
+        }
+    }
+}
+
+/// Removes `.` and `dir/..` without resolving links in the file system.
+fn lexical(path: &Path) -> PathBuf {
+    let mut lexical = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            Component::CurDir => {}
+            component => lexical.push(component),
+        }
+    }
+    lexical
+}
+
+/// Joins the components with `/`, dropping `dir/..` pairs.
+fn normalize(path: &Path) -> String {
+    let mut parts: Vec<String> = vec![];
+    for component in path.components() {
+        match component {
+            Component::ParentDir if parts.last().is_some_and(|last| last != "..") => {
+                parts.pop();
+            }
+            Component::CurDir => {}
+            component => parts.push(component.as_os_str().to_string_lossy().to_string()),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod link_tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::visit::{walk_text, Visitor};
+    use crate::{ModifierPolicy, Position};
+
+    #[test]
+    fn rewrites_and_checks_links() {
+        let main = "[a](setup.tmd#install-it) [b](setup.tmd#nope) [](gone.tmd)\n@[sub/part]\n";
+        let dir = TempDir::new(
+            "links",
+            &[
+                ("main.tmd", main),
+                ("setup.tmd", "# Install *it*\n"),
+                ("sub/part.tmd", "[c](../setup.tmd) [d](https://x.tmd)\n"),
+            ],
+        );
+
+        let (tree, diagnostics) = TurboTree::generate_file(dir.join("main.tmd")).unwrap();
+        struct Collect(Vec<(String, String)>);
+        impl Visitor for Collect {
+            fn visit_text(&mut self, text: &TurboText) {
+                if let TurboText::Link { alias, address, .. } = text {
+                    self.0.push((alias.clone().unwrap(), address.clone()));
+                }
+                walk_text(self, text)
+            }
+        }
+        let mut collect = Collect(vec![]);
+        collect.visit_tree(&tree);
+        let links = collect.0;
+        let link = |alias: &str, address: &str| (alias.to_string(), address.to_string());
+        // spans on the first line of `main`
+        let span = |link: &str| {
+            let start = main.find(link).unwrap();
+            let position = |offset| Position {
+                offset,
+                line: 1,
+                column: offset + 1,
+            };
+            Span::new(position(start), position(start + link.len()))
+        };
+        assert_eq!(
+            links,
+            vec![
+                link("a", "setup.html#install-it"),
+                link("b", "setup.html#nope"),
+                link("gone.tmd", "gone.html"),
+                link("c", "setup.html"),
+                link("d", "https://x.tmd"),
+            ]
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::MissingLinkAnchor {
+                    file: dir.join("main.tmd"),
+                    address: "setup.tmd#nope".to_string(),
+                    span: span("[b](setup.tmd#nope)"),
+                },
+                Diagnostic::MissingLinkTarget {
+                    file: dir.join("main.tmd"),
+                    address: "gone.tmd".to_string(),
+                    span: span("[](gone.tmd)"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stays_in_safe_root() {
+        let dir = TempDir::new(
+            "links-safe",
+            &[
+                (
+                    "docs/main.tmd",
+                    "[a](../secret.tmd#nope) [b](../gone.tmd) [c](ok.tmd#x) [d](sneaky.tmd)\n",
+                ),
+                ("secret.tmd", "# Secret\n"),
+                ("docs/ok.tmd", "# X\n"),
+                ("docs/sneaky.tmd", "@[../secret]\n"),
+            ],
+        );
+
+        let (_, diagnostics) =
+            TurboTree::generate_file_with_options(dir.join("docs/main.tmd"), &Options::safe())
+                .unwrap();
+        let messages = diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages[..2],
+            [
+                "link to `../secret.tmd#nope` is not checked: it is outside of the include root",
+                "link to `../gone.tmd` is not checked: it is outside of the include root",
+            ]
+        );
+        // the target is generated in the same root
+        assert!(
+            matches!(&diagnostics[2..], [Diagnostic::UncheckedLinkTarget { address, .. }] if address == "sneaky.tmd"),
+            "{diagnostics:?}"
+        );
+    }
+
+    #[test]
+    fn reports_unreadable_targets() {
+        let dir = TempDir::new(
+            "links-unread",
+            &[("main.tmd", "[a](open.tmd#b)\n"), ("open.tmd", "# *b\n")],
+        );
+
+        let options = Options {
+            modifiers: ModifierPolicy::Error,
+            ..Options::default()
+        };
+        let (_, diagnostics) =
+            TurboTree::generate_file_with_options(dir.join("main.tmd"), &options).unwrap();
+        match diagnostics.as_slice() {
+            [Diagnostic::UncheckedLinkTarget {
+                address, reason, ..
+            }] => {
+                assert_eq!(address, "open.tmd#b");
+                assert!(reason.contains("open.tmd"), "{reason}");
+            }
+            other => panic!("expected an unchecked target, got {other:?}"),
+        }
+        // with the default options the target is generated
+        let (_, diagnostics) = TurboTree::generate_file(dir.join("main.tmd")).unwrap();
+        assert_eq!(diagnostics, vec![]);
+    }
+}
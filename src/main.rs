@@ -1,37 +1,161 @@
 use crate::config::{Args, RunOption};
+use ariadne::{Label, Report, ReportKind, Source};
 use clap::Parser;
+use std::collections::HashMap;
 use std::io::Write;
-use turbo_md::{HtmlDefaults, TurboTree};
+use std::path::Path;
+use turbo_md::{
+    Diagnostic, Error, HtmlDefaults, HtmlRenderer, Options, ParseErrorKind, Renderer, SafeMode,
+    SectionNumbering, Severity, TocOptions, TurboTree,
+};
 
 mod config;
 
 fn main() {
     let args: Args = Args::parse();
+    let safe = (args.safe || args.include_root.is_some()).then(|| SafeMode {
+        include_root: args.include_root.clone(),
+        ..SafeMode::default()
+    });
+    let options = Options {
+        modifiers: args.modifiers.into(),
+        safe,
+        toc: TocOptions {
+            min_depth: args.toc_min,
+            max_depth: args.toc_max,
+            prepend: args.toc,
+        },
+        numbering: args.numbering.then(|| SectionNumbering {
+            start: args.numbering_start,
+            format: args.numbering_format.format(),
+        }),
+        ..Options::default()
+    };
 
     match args.option {
         RunOption::Html => {
             let file_name: &str = args.entry_file.split(".").collect::<Vec<&str>>()[0];
-            let parse = turbo_md::parse_file(&args.entry_file);
-            let ast = TurboTree::generate(parse);
+            let (ast, failed) = generate(&args.entry_file, &options);
             let title = file_name.to_string();
             let default_html = include_str!("../assets/defaults.html");
             let defaults = HtmlDefaults {
                 title,
                 default_html: default_html.to_string(),
             };
-            let html = ast.generate_html(Some(defaults));
+            let mut renderer = HtmlRenderer::new(Some(defaults)).with_permalinks(args.permalinks);
+            renderer.render(&ast).unwrap_or_else(|e| fail(e));
+            let html = renderer.finish();
             let mut output = std::fs::OpenOptions::new()
                 .write(true)
                 .truncate(true)
                 .create(true)
-                .open(&format!("{file_name}.html"))
+                .open(format!("{file_name}.html"))
                 .unwrap();
             output.write_all(html.as_bytes()).expect("lmao?");
+            if failed {
+                std::process::exit(1);
+            }
         }
         RunOption::Ast => {
-            let parse = turbo_md::parse_file(&args.entry_file);
-            let ast = TurboTree::generate(parse);
+            let (ast, failed) = generate(&args.entry_file, &options);
             println!("{}", ast);
+            if failed {
+                std::process::exit(1);
+            }
         }
     }
 }
+
+/// Generates the tree and reports its diagnostics, also returns whether any of
+/// them is an error.
+fn generate(entry_file: &str, options: &Options) -> (TurboTree, bool) {
+    let (ast, diagnostics) =
+        TurboTree::generate_file_with_options(entry_file, options).unwrap_or_else(|e| fail(e));
+    let mut sources = HashMap::new();
+    for diagnostic in &diagnostics {
+        report(diagnostic, diagnostic.severity(), &mut sources);
+    }
+    let failed = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity() == Severity::Error);
+    (ast, failed)
+}
+
+fn fail(error: Error) -> ! {
+    match error {
+        Error::Parse { file, errors } => {
+            let mut sources = HashMap::new();
+            for error in errors {
+                let diagnostic = Diagnostic::Parse {
+                    file: file.clone(),
+                    error,
+                };
+                report(&diagnostic, Severity::Error, &mut sources);
+            }
+        }
+        error => eprintln!("error: {error}"),
+    }
+    std::process::exit(1);
+}
+
+/// Prints the diagnostic with the source it points to, `sources` caches the
+/// files by name.
+fn report(diagnostic: &Diagnostic, severity: Severity, sources: &mut HashMap<String, String>) {
+    let kind = match severity {
+        Severity::Error => ReportKind::Error,
+        Severity::Warning => ReportKind::Warning,
+    };
+    let Some((Some(file), span)) = diagnostic.location() else {
+        let prefix = match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        eprintln!("{prefix}: {diagnostic}");
+        return;
+    };
+    let name = file.display().to_string();
+    let source = sources
+        .entry(name.clone())
+        .or_insert_with(|| std::fs::read_to_string(Path::new(file)).unwrap_or_default());
+    // ariadne counts characters, spans count bytes
+    let offset = |byte: usize| source.get(..byte).map_or(0, |text| text.chars().count());
+    let range = offset(span.start.offset)..offset(span.end.offset);
+    Report::build(kind, name.clone(), range.start)
+        .with_message(diagnostic)
+        .with_label(Label::new((name.clone(), range)).with_message(label(diagnostic)))
+        .finish()
+        .eprint((name, Source::from(source.as_str())))
+        .unwrap();
+}
+
+/// A short note on the source the diagnostic points to, the report itself
+/// carries the message.
+fn label(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic {
+        Diagnostic::Parse { error, .. } => match error.kind {
+            ParseErrorKind::Unexpected { .. } => "not expected here",
+            ParseErrorKind::UnclosedModifier(_) => "opened here",
+            ParseErrorKind::MismatchedModifier { .. } => "closed out of order",
+            ParseErrorKind::UnterminatedBlock => "block starts here",
+            ParseErrorKind::MalformedLink => "kept as text",
+            ParseErrorKind::MissingAltText => "image without alt text",
+            ParseErrorKind::UnknownImageAttribute(_) => "unknown attribute",
+            ParseErrorKind::ZeroSpan => "span of 0",
+            ParseErrorKind::UnsafeAddress(_) => "address removed",
+            ParseErrorKind::NestingTooDeep => "nested too deep",
+            ParseErrorKind::RawHtml | ParseErrorKind::StrayCaption => "kept as text",
+            ParseErrorKind::MalformedMacro | ParseErrorKind::ExtraTableCells => "left out",
+        },
+        Diagnostic::UndefinedFootnote { .. } => "referenced here",
+        Diagnostic::UnusedFootnote { .. } => "defined here",
+        Diagnostic::DuplicateFootnote { .. } => "defined again here",
+        Diagnostic::DuplicateHeadingId { .. } | Diagnostic::DuplicateFigureId { .. } => {
+            "used again here"
+        }
+        Diagnostic::UndefinedFigure { .. } => "referenced here",
+        Diagnostic::MissingLinkTarget { .. } | Diagnostic::MissingLinkAnchor { .. } => {
+            "link target not found"
+        }
+        Diagnostic::UncheckedLinkTarget { .. } => "link not checked",
+    }
+}
@@ -0,0 +1,51 @@
+/// A line `\name: expansion` of a `::: katex-macros` block, `None` if it is
+/// malformed. The expansion refers to arguments with `#1`, `#2`, ...
+pub(crate) fn macro_definition(line: &str) -> Option<(&str, &str)> {
+    let (name, expansion) = line.split_once(':')?;
+    let name = name.trim();
+    let command = name.strip_prefix('\\')?;
+    (!command.is_empty() && command.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| (name, expansion.trim()))
+}
+
+/// The definitions of a `::: katex-macros` block, blank and malformed lines
+/// are skipped.
+pub(crate) fn macro_definitions(code: &str) -> impl Iterator<Item = (&str, &str)> {
+    code.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(macro_definition)
+}
+
+#[cfg(test)]
+mod math_tests {
+    use crate::{parse_string, Diagnostic, ParseErrorKind, TurboTree};
+
+    #[test]
+    fn equations_and_macros() {
+        let input = "See @eq:euler.\n\n\
+                     ::: math\ne^{i\\pi} + 1 = 0\n:::\n\
+                     : {#eq:euler}\n\
+                     ::: math\n\\RR\n:::\n\
+                     ::: katex-macros\n\\RR: \\mathbb{R}\n\n\\norm: \\lVert#1\\rVert </script>\nRR\n:::\n";
+        let (parse, errors) = parse_string(input).unwrap();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (&error.kind, error.span.range()))
+                .collect::<Vec<_>>(),
+            [(&ParseErrorKind::MalformedMacro, 143..145)]
+        );
+        let (tree, diagnostics) = TurboTree::generate_with_diagnostics(parse).unwrap();
+        assert_eq!(diagnostics, Vec::<Diagnostic>::new());
+        assert_eq!(
+            tree.generate_html(None).unwrap(),
+            "<p>See <a class=\"reference\" href=\"#eq:euler\">Equation 1</a>.</p>\n\
+             <figure class=\"equation\" id=\"eq:euler\">\n\
+             <div class=\"katex\">\n$$\ne^{i\\pi} + 1 = 0\n\\tag{1}\n$$\n</div>\n</figure>\n\
+             <div class=\"katex\">\n$$\n\\RR\n$$\n</div>\n\
+             <script>window.katexMacros = Object.assign(window.katexMacros || {}, \
+             {\"\\\\RR\": \"\\\\mathbb{R}\", \
+             \"\\\\norm\": \"\\\\lVert#1\\\\rVert \\u003c/script>\"});</script>\n"
+        );
+    }
+}
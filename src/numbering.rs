@@ -0,0 +1,152 @@
+use crate::ast::{TurboText, TurboTree};
+use crate::visit::{walk_tree_mut, VisitorMut};
+
+/// Numbers headings by their size, like 1, 1.1 and 1.2.3. Headings written
+/// with `#* ` and headings in quotes are left out.
+#[derive(Debug, Clone, Copy)]
+pub struct SectionNumbering {
+    /// Size of the headings numbered on the first level, larger ones are not
+    /// numbered.
+    pub start: usize,
+    /// Formats the counters of the levels down to the heading. Sizes without a
+    /// heading in between are left out, `#### B` right after `## A` is 1.1.
+    pub format: fn(&[usize]) -> String,
+}
+
+impl Default for SectionNumbering {
+    fn default() -> Self {
+        Self {
+            start: 1,
+            format: dotted,
+        }
+    }
+}
+
+/// `1.2.3`, the default format.
+pub fn dotted(counters: &[usize]) -> String {
+    counters
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+pub(crate) fn number(content: &mut [TurboTree], numbering: &SectionNumbering) {
+    let mut counter = Counter {
+        numbering,
+        levels: vec![],
+    };
+    content
+        .iter_mut()
+        .for_each(|node| counter.visit_tree_mut(node));
+}
+
+struct Counter<'a> {
+    numbering: &'a SectionNumbering,
+    /// size of the heading that opened each level and its counter
+    levels: Vec<(usize, usize)>,
+}
+
+impl VisitorMut for Counter<'_> {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        match node {
+            TurboTree::Heading {
+                size,
+                numbered: true,
+                number,
+                ..
+            } if *size >= self.numbering.start => {
+                // closes the levels of this size and smaller ones, the last of
+                // them was the previous heading on the level of this one
+                let mut count = 0;
+                while let Some(&(open, counter)) = self.levels.last() {
+                    if open < *size {
+                        break;
+                    }
+                    count = counter;
+                    self.levels.pop();
+                }
+                self.levels.push((*size, count + 1));
+                let counters = self
+                    .levels
+                    .iter()
+                    .map(|&(_, counter)| counter)
+                    .collect::<Vec<_>>();
+                *number = Some((self.numbering.format)(&counters));
+            }
+            TurboTree::Quote { .. } => {}
+            node => walk_tree_mut(self, node),
+        }
+    }
+
+    fn visit_text_mut(&mut self, _text: &mut TurboText) {}
+}
+
+#[cfg(test)]
+mod numbering_tests {
+    use super::*;
+    use crate::{parse_string, Options, TocOptions};
+
+    fn generate(input: &str, numbering: SectionNumbering) -> TurboTree {
+        let options = Options {
+            numbering: Some(numbering),
+            toc: TocOptions {
+                prepend: true,
+                ..TocOptions::default()
+            },
+            ..Options::default()
+        };
+        let (parse, _) = parse_string(input).unwrap();
+        TurboTree::generate_with_options(parse, &options).unwrap().0
+    }
+
+    #[test]
+    fn numbers() {
+        let input = "# Title\n## A\n#### B\n### C\n#### E\n##* Appendix\n## *D*\n> ## quoted\n";
+        let tree = generate(
+            input,
+            SectionNumbering {
+                start: 2,
+                ..SectionNumbering::default()
+            },
+        );
+        let numbers = tree
+            .get_vec()
+            .unwrap()
+            .iter()
+            .filter_map(|node| match node {
+                TurboTree::Heading { number, .. } => Some(number.as_deref()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            numbers,
+            [
+                None,
+                Some("1"),
+                Some("1.1"),
+                Some("1.2"),
+                Some("1.2.1"),
+                None,
+                Some("2")
+            ]
+        );
+
+        let html = generate(
+            "# A\n## B\n",
+            SectionNumbering {
+                format: |counters| format!("§{}", dotted(counters)),
+                ..SectionNumbering::default()
+            },
+        )
+        .generate_html(None)
+        .unwrap();
+        assert_eq!(
+            html,
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#a\">§1 A</a>\n\
+             <ul>\n<li><a href=\"#b\">§1.1 B</a></li>\n</ul>\n</li>\n</ul>\n</nav>\n\
+             <h1 id=\"a\"><span class=\"number\">§1</span> A</h1>\n\
+             <h2 id=\"b\"><span class=\"number\">§1.1</span> B</h2>\n"
+        );
+    }
+}
@@ -1,61 +1,189 @@
-use crate::{Code, Lang, ListKind, TurboTextMod};
+use crate::diagnostic::{ParseError, ParseErrorKind};
+use crate::math::macro_definition;
+use crate::span::{spanned_chars, Position, Span};
+use crate::{Alignment, Code, Image, Lang, ListKind, TurboTextMod};
 use chumsky::prelude::*;
+use chumsky::Stream;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Turbo {
-    Root(Vec<Turbo>),
+    Root {
+        content: Vec<Turbo>,
+        span: Span,
+    },
     Header {
         ident: usize,
         size: usize,
+        /// `false` for `#* `, which leaves the heading out of the numbering
+        numbered: bool,
         text: TurboTextRaw,
+        span: Span,
     },
     Horizontal {
         ident: usize,
+        span: Span,
+    },
+    Empty {
+        span: Span,
     },
-    Empty,
     Line {
         ident: usize,
         text: TurboTextRaw,
+        span: Span,
     },
     ListElemStart {
         ident: usize,
         kind: ListKind,
+        number: isize,
+        marker: Option<String>,
         check: Option<bool>,
         content: Vec<Turbo>,
+        span: Span,
     },
     Code {
         ident: Option<usize>,
         code: Code,
+        span: Span,
     },
     Include {
         ident: usize,
         path: String,
+        span: Span,
+    },
+    Quote {
+        ident: usize,
+        content: Vec<Turbo>,
+        attribution: Option<TurboTextRaw>,
+        span: Span,
+    },
+    FootnoteDef {
+        ident: usize,
+        name: String,
+        text: TurboTextRaw,
+        span: Span,
     },
+    Table {
+        ident: usize,
+        header: Vec<Vec<TableCellRaw>>,
+        alignments: Vec<Alignment>,
+        rows: Vec<Vec<TableCellRaw>>,
+        span: Span,
+    },
+    /// `: <text>`, the caption of the image, table or code block before it
+    Caption {
+        ident: usize,
+        text: TurboTextRaw,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableCellRaw {
+    pub text: TurboTextRaw,
+    pub colspan: usize,
+    pub rowspan: usize,
+}
+
+impl TableCellRaw {
+    fn new(text: TurboTextRaw) -> Self {
+        Self {
+            text,
+            colspan: 1,
+            rowspan: 1,
+        }
+    }
 }
 
 impl Turbo {
     pub fn line(&self) -> Option<(&usize, &TurboTextRaw)> {
         match self {
-            Turbo::Line { ident, text } => Some((ident, text)),
+            Turbo::Line { ident, text, .. } => Some((ident, text)),
             _ => None,
         }
     }
+
+    /// The source lines of the block, indentation and line break included.
+    pub fn span(&self) -> Span {
+        match self {
+            Turbo::Root { span, .. }
+            | Turbo::Header { span, .. }
+            | Turbo::Horizontal { span, .. }
+            | Turbo::Empty { span }
+            | Turbo::Line { span, .. }
+            | Turbo::ListElemStart { span, .. }
+            | Turbo::Code { span, .. }
+            | Turbo::Include { span, .. }
+            | Turbo::Quote { span, .. }
+            | Turbo::FootnoteDef { span, .. }
+            | Turbo::Table { span, .. }
+            | Turbo::Caption { span, .. } => *span,
+        }
+    }
 }
 
 pub type TurboTextRaw = Vec<TurboInlineRaw>;
 
 #[derive(Debug, Clone, PartialEq, Hash)]
 pub enum TurboInlineRaw {
-    NewLine,
-    ModFlag(TurboTextMod),
+    NewLine {
+        span: Span,
+    },
+    ModFlag {
+        kind: TurboTextMod,
+        span: Span,
+    },
     Link {
         alias: Option<String>,
         address: String,
+        span: Span,
+    },
+    Image {
+        image: Image,
+        span: Span,
+    },
+    FootnoteRef {
+        name: String,
+        span: Span,
+    },
+    /// `@fig:arch`, a reference to the figure labeled `fig:arch`.
+    FigureRef {
+        id: String,
+        span: Span,
+    },
+    Text {
+        text: String,
+        span: Span,
+    },
+    Math {
+        math: String,
+        span: Span,
+    },
+    /// `\html{...}`, passed through to HTML as it is.
+    Html {
+        html: String,
+        span: Span,
     },
-    Text(String),
 }
 
-pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
+impl TurboInlineRaw {
+    pub fn span(&self) -> Span {
+        match self {
+            TurboInlineRaw::NewLine { span }
+            | TurboInlineRaw::ModFlag { span, .. }
+            | TurboInlineRaw::Link { span, .. }
+            | TurboInlineRaw::Image { span, .. }
+            | TurboInlineRaw::FootnoteRef { span, .. }
+            | TurboInlineRaw::FigureRef { span, .. }
+            | TurboInlineRaw::Text { span, .. }
+            | TurboInlineRaw::Math { span, .. }
+            | TurboInlineRaw::Html { span, .. } => *span,
+        }
+    }
+}
+
+/// Inline Turbo text. Plain text additionally stops at any of the `stop` characters,
+/// so that embedded formats can reuse the inline syntax.
+fn inline(stop: &'static [char]) -> impl Parser<char, TurboInlineRaw, Error = ParseError> + Clone {
     let plain_text = filter(|c| {
         *c != '#'
             && *c != '*'
@@ -67,27 +195,19 @@ pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
             && *c != '['
             && *c != '\n'
             && *c != '\\'
+            && *c != '$'
+            && *c != '!'
+            && *c != '@'
+            && !stop.contains(c)
     })
     .repeated()
     .at_least(1)
     .collect::<String>()
-    .map(TurboInlineRaw::Text);
+    .map_with_span(|text, span| TurboInlineRaw::Text { text, span });
 
     let extended_backslash_text = filter(|c| *c != '\n' && *c != '{' && *c != '}')
         .repeated()
-        .collect::<String>()
-        .map(TurboInlineRaw::Text);
-
-    let number = just::<_, char, Simple<char>>('-')
-        .or_not()
-        .then(text::digits(10))
-        .map(|(neg, number)| {
-            if neg.is_some() {
-                -number.parse::<isize>().unwrap()
-            } else {
-                number.parse::<isize>().unwrap()
-            }
-        });
+        .collect::<String>();
 
     let text_modifier = choice((
         just('*').repeated().exactly(2).to(TurboTextMod::Cursive),
@@ -98,23 +218,57 @@ pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
         just('~').to(TurboTextMod::Strike),
         just('`').to(TurboTextMod::Code),
     ))
-    .map(TurboInlineRaw::ModFlag);
+    .map_with_span(|kind, span| TurboInlineRaw::ModFlag { kind, span });
 
     let backslash_extended = just('\\')
         .ignore_then(just('{'))
         .ignore_then(extended_backslash_text)
-        .then_ignore(just('}'));
+        .then_ignore(just('}'))
+        .map_with_span(|text, span| TurboInlineRaw::Text { text, span });
+
+    let raw_html = just("\\html{")
+        .ignore_then(extended_backslash_text)
+        .then_ignore(just('}'))
+        .map_with_span(|html, span| TurboInlineRaw::Html { html, span });
 
     let backslash = just('\\')
         .ignore_then(any())
-        .map(|val| TurboInlineRaw::Text(val.to_string()));
+        .map_with_span(|val: char, span| TurboInlineRaw::Text {
+            text: val.to_string(),
+            span,
+        });
 
-    let new_line = just('\\').then(just('\n')).to(TurboInlineRaw::NewLine);
+    let new_line = just('\\')
+        .then(just('\n'))
+        .map_with_span(|_, span| TurboInlineRaw::NewLine { span });
+
+    let footnote_ref =
+        footnote_name().map_with_span(|name, span| TurboInlineRaw::FootnoteRef { name, span });
+
+    // the kind before the colon is required, so addresses like `a@b.c` stay text
+    let figure_ref = just('@')
+        .ignore_then(
+            filter(|c: &char| c.is_alphanumeric() || *c == '-' || *c == '_')
+                .repeated()
+                .at_least(1)
+                .collect::<String>()
+                .separated_by(just(':'))
+                .at_least(2),
+        )
+        .map_with_span(|parts, span| TurboInlineRaw::FigureRef {
+            id: parts.join(":"),
+            span,
+        });
+
+    let at = just('@').map_with_span(|val, span| TurboInlineRaw::Text {
+        text: val.to_string(),
+        span,
+    });
 
     let link = filter(|c| *c != '\n' && *c != ']')
         .repeated()
         .collect::<String>()
-        .map(|val| if val == "" { None } else { Some(val) })
+        .map(|val| if val.is_empty() { None } else { Some(val) })
         .delimited_by(just('['), just(']'))
         .then(
             filter(|c| *c != '\n' && *c != ')')
@@ -122,16 +276,159 @@ pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
                 .collect::<String>()
                 .delimited_by(just('('), just(')')),
         )
-        .map(|(alias, address)| TurboInlineRaw::Link { alias, address });
+        .map_with_span(|(alias, address), span| TurboInlineRaw::Link {
+            alias,
+            address,
+            span,
+        });
+
+    let math = filter(|c| *c != '\n' && *c != '$')
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .delimited_by(just('$'), just('$'))
+        .map_with_span(|math, span| TurboInlineRaw::Math { math, span });
+
+    let dollar = just('$').map_with_span(|val, span| TurboInlineRaw::Text {
+        text: val.to_string(),
+        span,
+    });
 
-    let inline = choice((
+    let image_title = just(' ')
+        .repeated()
+        .at_least(1)
+        .ignore_then(
+            filter(|c| *c != '\n' && *c != '"')
+                .repeated()
+                .collect::<String>()
+                .delimited_by(just('"'), just('"')),
+        )
+        .then_ignore(just(' ').repeated());
+
+    let image_attribute = text::ident()
+        .then_ignore(just('='))
+        .then(
+            filter(|c: &char| c.is_alphanumeric() || *c == '%' || *c == '.')
+                .repeated()
+                .at_least(1)
+                .collect::<String>(),
+        )
+        .padded_by(just(' ').repeated());
+
+    let image = just('!')
+        .ignore_then(
+            filter(|c| *c != '\n' && *c != ']')
+                .repeated()
+                .collect::<String>()
+                .map(|val| if val.is_empty() { None } else { Some(val) })
+                .delimited_by(just('['), just(']')),
+        )
+        .then(
+            filter(|c| *c != '\n' && *c != ')' && *c != ' ')
+                .repeated()
+                .at_least(1)
+                .collect::<String>()
+                .then(image_title.or_not())
+                .delimited_by(just('('), just(')')),
+        )
+        .then(
+            image_attribute
+                .separated_by(just(','))
+                .delimited_by(just('{'), just('}'))
+                .or_not(),
+        )
+        .validate(|((alt, (address, title)), attributes), span: Span, emit| {
+            if alt.is_none() {
+                emit(ParseError::new(ParseErrorKind::MissingAltText, span))
+            }
+            let mut image = Image {
+                alt,
+                address,
+                title,
+                width: None,
+                height: None,
+            };
+            for (key, value) in attributes.unwrap_or_default() {
+                match key.as_str() {
+                    "width" => image.width = Some(value),
+                    "height" => image.height = Some(value),
+                    _ => emit(ParseError::new(
+                        ParseErrorKind::UnknownImageAttribute(key),
+                        span,
+                    )),
+                }
+            }
+            TurboInlineRaw::Image { image, span }
+        });
+
+    let exclamation = just('!').map_with_span(|val, span| TurboInlineRaw::Text {
+        text: val.to_string(),
+        span,
+    });
+
+    // `#` and `|` only mean something at the start of a line
+    let literal = filter(move |c| (*c == '#' || *c == '|') && !stop.contains(c)).map_with_span(
+        |val: char, span| TurboInlineRaw::Text {
+            text: val.to_string(),
+            span,
+        },
+    );
+
+    let malformed_link = just('[').validate(|val, span, emit| {
+        emit(ParseError::new(ParseErrorKind::MalformedLink, span));
+        TurboInlineRaw::Text {
+            text: val.to_string(),
+            span,
+        }
+    });
+
+    choice((
         new_line,
-        backslash,
+        raw_html,
         backslash_extended,
+        backslash,
+        image,
+        exclamation,
+        footnote_ref,
+        figure_ref,
+        at,
         link,
+        malformed_link,
+        math,
+        dollar,
         text_modifier,
+        literal,
         plain_text,
-    ));
+    ))
+}
+
+/// Parser without limits.
+#[cfg(test)]
+pub fn parser() -> impl Parser<char, Turbo, Error = ParseError> {
+    limited_parser(usize::MAX)
+}
+
+/// Quotes nested deeper than `max_depth` are reported and left empty.
+// `try_map` has to return chumsky's error, spans make it large
+#[allow(clippy::result_large_err)]
+pub(crate) fn limited_parser(max_depth: usize) -> impl Parser<char, Turbo, Error = ParseError> {
+    let number = just::<_, char, ParseError>('-')
+        .or_not()
+        .then(text::digits(10))
+        // a number too large isn't a marker, the item text starts with it
+        .try_map(|(neg, number), span| {
+            let number = if neg.is_some() {
+                format!("-{number}")
+            } else {
+                number
+            };
+            number
+                .parse::<isize>()
+                .map_err(|_| ParseError::expected_input_found(span, None, None))
+        });
+
+    let cell = inline(&['|']).repeated();
+    let inline = inline(&[]);
 
     let whitespace = just(' ').repeated().collect::<String>().map(|s| s.len());
 
@@ -139,37 +436,91 @@ pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
 
     let ident_text_line = whitespace
         .then(text_line.clone())
-        .map(|(ident, text)| Turbo::Line { ident, text });
+        .map_with_span(|(ident, text), span| Turbo::Line { ident, text, span });
 
+    // `#*bold*` is a heading with bold text, the space is required after `#*`
+    let unnumbered = just("* ");
     let header_tag = filter(|c| *c == '#')
         .repeated()
         .at_least(1)
         .collect::<String>()
+        .then(unnumbered.or_not())
         .then_ignore(just(' ').or_not())
-        .map(|tag| tag.len());
+        .map(|(tag, unnumbered)| (tag.len(), unnumbered.is_none()));
 
     let header = whitespace
-        .clone()
         .then(header_tag.then(text_line.clone()))
-        .map(|(ident, (size, text))| Turbo::Header { ident, size, text });
+        .map_with_span(|(ident, ((size, numbered), text)), span| Turbo::Header {
+            ident,
+            size,
+            numbered,
+            text,
+            span,
+        });
 
     let hr = whitespace
-        .clone()
         .then(just('-').repeated().at_least(3).ignore_then(just('\n')))
-        .map(|(ident, _)| Turbo::Horizontal { ident });
+        .map_with_span(|(ident, _), span| Turbo::Horizontal { ident, span });
+
+    let empty = just('\n').map_with_span(|_, span| Turbo::Empty { span });
+
+    let roman = |digits: &'static str| {
+        filter(move |c: &char| digits.contains(*c))
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .try_map(|numeral, span| {
+                // single letters other than i, v and x are read as alphabet markers
+                let single = numeral.len() == 1 && !"ivxIVX".contains(&numeral);
+                roman_value(&numeral)
+                    .filter(|_| !single)
+                    .ok_or_else(|| ParseError::expected_input_found(span, None, None))
+            })
+    };
+
+    let alphabet = |first: char, last: char| {
+        filter(move |c: &char| (first..=last).contains(c))
+            .map(move |c| c as isize - first as isize + 1)
+    };
 
-    let empty = just('\n').to(Turbo::Empty);
+    let list_marker = choice((
+        number.map(|number| (ListKind::Numbered, number)),
+        roman("ivxlcdm").map(|number| (ListKind::RomanLower, number)),
+        roman("IVXLCDM").map(|number| (ListKind::RomanUpper, number)),
+        alphabet('a', 'z').map(|number| (ListKind::AlphabetLower, number)),
+        alphabet('A', 'Z').map(|number| (ListKind::AlphabetUpper, number)),
+    ))
+    .then_ignore(just('.'))
+    .then_ignore(just(' '));
+
+    let list_style = choice((
+        text::keyword("none").to(0),
+        text::keyword("circle").to(1),
+        text::keyword("disc").to(2),
+        text::keyword("square").to(3),
+    ))
+    .delimited_by(just('{'), just('}'))
+    .then_ignore(just(' '));
 
-    let list_tag = choice((
-        just('-')
-            .then(just(' ').or_not())
-            .to(ListKind::Unordered(None)),
-        just('-')
-            .ignore_then(just(' ').or_not())
-            .ignore_then(number)
-            .then_ignore(just(' ').or_not())
-            .to(ListKind::Numbered),
-    ));
+    // quoted, so item text starting with brackets or a footnote stays text
+    let custom_marker = filter(|c| *c != '\n' && *c != '"')
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .delimited_by(just("{\""), just("\"}"))
+        .then_ignore(just(' '));
+
+    let list_tag = just('-')
+        .ignore_then(just(' ').or_not())
+        .ignore_then(
+            choice((
+                list_marker.map(|(kind, number)| (kind, number, None)),
+                list_style.map(|style| (ListKind::Unordered(Some(style)), 1, None)),
+                custom_marker.map(|marker| (ListKind::Custom, 1, Some(marker))),
+            ))
+            .or_not(),
+        )
+        .map(|marker| marker.unwrap_or((ListKind::Unordered(None), 1, None)));
 
     let check = choice((just('x'), just(' ')))
         .delimited_by(just('['), just(']'))
@@ -190,71 +541,527 @@ pub fn parser() -> impl Parser<char, Turbo, Error = Simple<char>> {
 
     let code_end = just(':').repeated().exactly(3).then(just('\n').or_not());
 
-    let code = code_start
-        .then(take_until(code_end))
-        .map(|(lang, (code, _))| Turbo::Code {
-            ident: None,
-            code: Code {
-                lang: Lang::from(lang.as_ref()),
-                code: code.iter().collect::<String>(),
-            },
-        });
+    let code_body = code_start
+        .then(take_until(code_end).map_with_span(|(code, _), span: Span| (code, span.start)));
+
+    let code = code_body.validate(|(lang, (code, start)), span, emit| {
+        code_block(None, &lang, code, start, span, emit)
+    });
 
     let code_ident =
         whitespace
-            .then(code_start.then(take_until(code_end)))
-            .map(|(ident, (lang, (code, _)))| Turbo::Code {
-                ident: Some(ident),
-                code: Code {
-                    lang: Lang::from(lang.as_ref()),
-                    code: code.iter().collect::<String>(),
-                },
+            .then(code_body)
+            .validate(|(ident, (lang, (code, start))), span, emit| {
+                code_block(Some(ident), &lang, code, start, span, emit)
             });
 
+    let unterminated_code = whitespace
+        .then(code_start.map_with_span(|lang, span: Span| (lang, span)))
+        .then(
+            any()
+                .repeated()
+                .map_with_span(|code, span: Span| (code, span.start)),
+        )
+        .validate(|((ident, (lang, opening)), (code, start)), span, emit| {
+            emit(ParseError::new(ParseErrorKind::UnterminatedBlock, opening));
+            code_block(Some(ident), &lang, code, start, span, emit)
+        });
+
+    let table_row = whitespace
+        .ignore_then(just('|'))
+        .ignore_then(
+            cell.map_with_span(|cell, span: Span| {
+                let cell = trim_cell(cell);
+                // without the padding
+                let span = match (cell.first(), cell.last()) {
+                    (Some(first), Some(last)) => first.span().join(last.span()),
+                    _ => span,
+                };
+                (TableCellRaw::new(cell), span)
+            })
+            .then_ignore(just('|'))
+            .repeated()
+            .at_least(1),
+        )
+        .then_ignore(just(' ').repeated())
+        .then_ignore(text::newline());
+
+    let table_alignment = just(':')
+        .or_not()
+        .then_ignore(just('-').repeated().at_least(1))
+        .then(just(':').or_not())
+        .padded_by(just(' ').repeated())
+        .map(|(left, right)| match (left, right) {
+            (Some(_), Some(_)) => Alignment::Center,
+            (Some(_), None) => Alignment::Left,
+            (None, Some(_)) => Alignment::Right,
+            (None, None) => Alignment::Default,
+        });
+
+    let table_separator = whitespace
+        .ignore_then(just('|'))
+        .ignore_then(
+            table_alignment
+                .then_ignore(just('|'))
+                .repeated()
+                .at_least(1),
+        )
+        .then_ignore(just(' ').repeated())
+        .then_ignore(text::newline());
+
+    let table = whitespace
+        .rewind()
+        .then(table_row.clone())
+        .then(table_separator)
+        .then(table_row.repeated())
+        .validate(|(((ident, header), mut alignments), rows), span, emit| {
+            let header = header.into_iter().map(|(cell, _)| cell).collect::<Vec<_>>();
+            alignments.resize(header.len(), Alignment::Default);
+            let rows = rows
+                .into_iter()
+                .map(|row| {
+                    if let (Some((_, first)), Some((_, last))) = (row.get(header.len()), row.last())
+                    {
+                        emit(ParseError::new(
+                            ParseErrorKind::ExtraTableCells,
+                            first.join(*last),
+                        ));
+                    }
+                    let mut row = row.into_iter().map(|(cell, _)| cell).collect::<Vec<_>>();
+                    row.resize(header.len(), TableCellRaw::new(vec![]));
+                    row
+                })
+                .collect();
+            Turbo::Table {
+                ident,
+                header: vec![header],
+                alignments,
+                rows,
+                span,
+            }
+        });
+
     let list_element_start = whitespace
         .then(list_tag)
         .then(check.or_not())
         .then(choice((
             code,
-            text_line.map(|text| Turbo::Line { ident: 0, text }),
+            text_line.clone().map_with_span(|text, span| Turbo::Line {
+                ident: 0,
+                text,
+                span,
+            }),
         )))
-        .map(|(((ident, kind), check), content)| Turbo::ListElemStart {
-            ident,
-            kind,
-            check,
-            content: vec![content],
-        });
+        .map_with_span(
+            |(((ident, (kind, number, marker)), check), content), span| Turbo::ListElemStart {
+                ident,
+                kind,
+                number,
+                marker,
+                check,
+                content: vec![content],
+                span,
+            },
+        );
 
     let include = whitespace
         .then(
             just('@')
                 .ignore_then(
-                    any()
+                    filter(|c| *c != '\n' && *c != ']')
                         .repeated()
+                        .at_least(1)
                         .collect::<String>()
                         .delimited_by(just('['), just(']')),
                 )
                 .then_ignore(just('\n')),
         )
-        .map(|(ident, path)| Turbo::Include { ident, path });
+        .map_with_span(|(ident, path), span| Turbo::Include { ident, path, span });
+
+    let quote_line = whitespace
+        .then_ignore(just('>'))
+        .then_ignore(just(' ').or_not())
+        .then(
+            filter(|c| *c != '\n')
+                .map_with_span(|c, span: Span| (c, span))
+                .repeated()
+                .chain(just('\n').map_with_span(|c, span: Span| (c, span))),
+        );
+
+    let quote = quote_line
+        .repeated()
+        .at_least(1)
+        .validate(move |lines, span, emit| quote_block(lines, span, max_depth, emit));
+
+    let caption = whitespace
+        .then_ignore(just(": "))
+        .then(text_line.clone())
+        .map_with_span(|(ident, text), span| Turbo::Caption { ident, text, span });
+
+    let footnote_def = whitespace
+        .then(footnote_name())
+        .then_ignore(just(':'))
+        .then_ignore(just(' ').repeated())
+        .then(text_line.clone())
+        .map_with_span(|((ident, name), text), span| Turbo::FootnoteDef {
+            ident,
+            name,
+            text,
+            span,
+        });
 
     choice((
         header,
         hr,
         empty,
         code_ident,
+        unterminated_code,
         include,
+        quote,
+        footnote_def,
+        caption,
+        table,
         list_element_start,
         ident_text_line,
     ))
+    // a line nothing matches is reported and skipped instead of ending the document
+    .recover_with(skip_until(['\n'], |span| Turbo::Empty { span }).consume_end())
     .repeated()
-    .map(Turbo::Root)
+    .then_ignore(end())
+    .map_with_span(|content, span| Turbo::Root { content, span })
+}
+
+fn roman_value(numeral: &str) -> Option<isize> {
+    let digit = |c: char| match c.to_ascii_lowercase() {
+        'i' => 1,
+        'v' => 5,
+        'x' => 10,
+        'l' => 50,
+        'c' => 100,
+        'd' => 500,
+        'm' => 1000,
+        _ => 0,
+    };
+    let digits = numeral.chars().map(digit).collect::<Vec<isize>>();
+    let mut value = 0;
+    for (idx, digit) in digits.iter().enumerate() {
+        match digits.get(idx + 1) {
+            Some(next) if next > digit => value -= digit,
+            _ => value += digit,
+        }
+    }
+    // only accept the canonical spelling, so `iiii` or `vx` are rejected
+    (to_roman(value) == numeral.to_ascii_lowercase()).then_some(value)
+}
+
+fn to_roman(mut value: isize) -> String {
+    const NUMERALS: [(isize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut result = String::new();
+    for (number, numeral) in NUMERALS {
+        while value >= number {
+            result.push_str(numeral);
+            value -= number;
+        }
+    }
+    result
+}
+
+fn footnote_name() -> impl Parser<char, String, Error = ParseError> + Copy {
+    just('[')
+        .ignore_then(just('^'))
+        .ignore_then(
+            filter(|c| *c != '\n' && *c != ']' && *c != ' ')
+                .repeated()
+                .at_least(1)
+                .collect::<String>(),
+        )
+        .then_ignore(just(']'))
+}
+
+type SpannedLine = Vec<(char, Span)>;
+
+/// Parses the `>`-stripped lines of a quote as their own document, keeping the
+/// original spans. A trailing `-- Author` line becomes the attribution.
+fn quote_block(
+    mut lines: Vec<(usize, SpannedLine)>,
+    span: Span,
+    max_depth: usize,
+    emit: &mut dyn FnMut(ParseError),
+) -> Turbo {
+    let ident = lines[0].0;
+    if max_depth == 0 {
+        emit(ParseError::new(ParseErrorKind::NestingTooDeep, span));
+        return Turbo::Quote {
+            ident,
+            content: vec![],
+            attribution: None,
+            span,
+        };
+    }
+
+    let mut attribution = None;
+    let last = lines.last().map(|(_, line)| line).unwrap();
+    if last.iter().take(3).map(|(c, _)| *c).eq("-- ".chars()) {
+        let (_, line) = lines.pop().unwrap();
+        let text = line[3..line.len() - 1].to_vec();
+        let eoi = line[line.len() - 1].1;
+        let (text, errors) = inline(&[])
+            .repeated()
+            .then_ignore(end())
+            .parse_recovery(Stream::from_iter(eoi, text.into_iter()));
+        errors.into_iter().for_each(&mut *emit);
+        attribution = text;
+    }
+
+    let body = lines
+        .into_iter()
+        .flat_map(|(_, line)| line)
+        .collect::<SpannedLine>();
+    let eoi = Span::new(span.end, span.end);
+    let (content, errors) =
+        limited_parser(max_depth - 1).parse_recovery(Stream::from_iter(eoi, body.into_iter()));
+    errors.into_iter().for_each(emit);
+
+    let content = match content {
+        Some(Turbo::Root { content, .. }) => content,
+        _ => vec![],
+    };
+    Turbo::Quote {
+        ident,
+        content,
+        attribution,
+        span,
+    }
+}
+
+/// `start` is the position of the first character of `code`.
+fn code_block(
+    ident: Option<usize>,
+    lang: &str,
+    code: Vec<char>,
+    start: Position,
+    span: Span,
+    emit: &mut dyn FnMut(ParseError),
+) -> Turbo {
+    let lang = Lang::from(lang);
+    if lang == Lang::KaTeXMacros {
+        check_macros(&code, start, emit);
+    }
+    if lang == Lang::Table {
+        // spans continue from the block start, so errors point into the block
+        let end = code.iter().fold(start, |position, c| position.advance(*c));
+        let stream = Stream::from_iter(
+            Span::new(end, end),
+            spanned_chars(code.iter().copied(), start),
+        );
+        let (table, errors) = table_code().parse_recovery(stream);
+        errors.into_iter().for_each(emit);
+        if let Some((header, alignments, rows)) = table {
+            return Turbo::Table {
+                ident: ident.unwrap_or(0),
+                header,
+                alignments,
+                rows,
+                span,
+            };
+        }
+    }
+    Turbo::Code {
+        ident,
+        code: Code {
+            lang,
+            code: code.iter().collect::<String>(),
+        },
+        span,
+    }
+}
+
+/// Reports the lines of a `::: katex-macros` block that define nothing.
+fn check_macros(code: &[char], start: Position, emit: &mut dyn FnMut(ParseError)) {
+    let mut position = start;
+    for line in code.split_inclusive(|c| *c == '\n') {
+        let line_start = position;
+        let text = line.iter().collect::<String>();
+        let text = text.trim_end_matches('\n');
+        position = line
+            .iter()
+            .fold(position, |position, c| position.advance(*c));
+        if !text.trim().is_empty() && macro_definition(text).is_none() {
+            let end = text
+                .chars()
+                .fold(line_start, |position, c| position.advance(c));
+            emit(ParseError::new(
+                ParseErrorKind::MalformedMacro,
+                Span::new(line_start, end),
+            ));
+        }
+    }
+}
+
+type TableRaw = (
+    Vec<Vec<TableCellRaw>>,
+    Vec<Alignment>,
+    Vec<Vec<TableCellRaw>>,
+);
+
+#[derive(Debug, Clone)]
+enum TableEntry {
+    Header(Vec<Vec<TableCellRaw>>),
+    Rows(Vec<Vec<TableCellRaw>>),
+    Align(Vec<Alignment>),
+}
+
+#[derive(Debug, Clone)]
+enum TableCellEntry {
+    Text(TurboTextRaw),
+    Colspan(usize),
+    Rowspan(usize),
+}
+
+/// Body of a `::: table` block:
+/// ```text
+/// {
+///   align: [left, center],
+///   header: [[Name, Value]],
+///   rows: [
+///     [{text: *a*, rowspan: 2}, 1],
+///     [2],
+///   ],
+/// }
+/// ```
+fn table_code() -> impl Parser<char, TableRaw, Error = ParseError> {
+    let key = |name: &'static str| text::keyword(name).then(just(':').padded());
+
+    let cell_text = inline(&[',', '{', '}', ']']).repeated().map(trim_cell);
+
+    let span_count = text::int(10).validate(|count: String, span, emit| {
+        let count = count.parse::<usize>().unwrap_or(0);
+        if count == 0 {
+            emit(ParseError::new(ParseErrorKind::ZeroSpan, span));
+        }
+        count.max(1)
+    });
+
+    let cell_entry = choice((
+        key("text")
+            .ignore_then(cell_text.clone())
+            .map(TableCellEntry::Text),
+        key("colspan")
+            .ignore_then(span_count)
+            .map(TableCellEntry::Colspan),
+        key("rowspan")
+            .ignore_then(span_count)
+            .map(TableCellEntry::Rowspan),
+    ))
+    .padded();
+
+    let cell_object = cell_entry
+        .separated_by(just(',').padded())
+        .allow_trailing()
+        .delimited_by(just('{'), just('}'))
+        .map(|entries| {
+            let mut cell = TableCellRaw::new(vec![]);
+            for entry in entries {
+                match entry {
+                    TableCellEntry::Text(text) => cell.text = text,
+                    TableCellEntry::Colspan(count) => cell.colspan = count,
+                    TableCellEntry::Rowspan(count) => cell.rowspan = count,
+                }
+            }
+            cell
+        });
+
+    let cell = choice((cell_object, cell_text.map(TableCellRaw::new))).padded();
+
+    let rows = cell
+        .separated_by(just(','))
+        .delimited_by(just('['), just(']'))
+        .padded()
+        .separated_by(just(',').padded())
+        .allow_trailing()
+        .delimited_by(just('['), just(']'));
+
+    let alignment = choice((
+        text::keyword("default").to(Alignment::Default),
+        text::keyword("left").to(Alignment::Left),
+        text::keyword("center").to(Alignment::Center),
+        text::keyword("right").to(Alignment::Right),
+    ))
+    .padded()
+    .separated_by(just(','))
+    .allow_trailing()
+    .delimited_by(just('['), just(']'));
+
+    let entry = choice((
+        key("header")
+            .ignore_then(rows.clone())
+            .map(TableEntry::Header),
+        key("rows").ignore_then(rows).map(TableEntry::Rows),
+        key("align").ignore_then(alignment).map(TableEntry::Align),
+    ))
+    .padded();
+
+    entry
+        .separated_by(just(',').padded())
+        .allow_trailing()
+        .delimited_by(just('{'), just('}'))
+        .padded()
+        .then_ignore(end())
+        .map(|entries| {
+            let mut table: TableRaw = (vec![], vec![], vec![]);
+            for entry in entries {
+                match entry {
+                    TableEntry::Header(header) => table.0.extend(header),
+                    TableEntry::Align(alignments) => table.1 = alignments,
+                    TableEntry::Rows(rows) => table.2.extend(rows),
+                }
+            }
+            table
+        })
+}
+
+/// Trims the cell text, keeping the spans on the trimmed text.
+fn trim_cell(mut cell: TurboTextRaw) -> TurboTextRaw {
+    if let Some(TurboInlineRaw::Text { text, span }) = cell.first_mut() {
+        let trimmed = text.trim_start();
+        span.start = text[..text.len() - trimmed.len()]
+            .chars()
+            .fold(span.start, Position::advance);
+        *text = trimmed.to_string();
+    }
+    if let Some(TurboInlineRaw::Text { text, span }) = cell.last_mut() {
+        // cell text never spans lines, so the end moves back within its line
+        let trimmed = text.trim_end();
+        let removed = &text[trimmed.len()..];
+        span.end.offset -= removed.len();
+        span.end.column -= removed.chars().count();
+        *text = trimmed.to_string();
+    }
+    cell.retain(|inline| !matches!(inline, TurboInlineRaw::Text { text, .. } if text.is_empty()));
+    cell
 }
 
 #[cfg(test)]
 mod parser_tests {
     use super::*;
+    use crate::span::stream;
+    use crate::Severity;
     use chumsky::Parser;
+    use std::ops::Range;
 
     const INPUT: &str = r#"
 # Test
@@ -282,12 +1089,372 @@ fn main() {
 @[chapter1]
 "#;
 
+    /// Span of `range` in `input`.
+    fn span(input: &str, range: Range<usize>) -> Span {
+        let position = |offset: usize| {
+            input[..offset]
+                .chars()
+                .fold(Position::default(), Position::advance)
+        };
+        Span::new(position(range.start), position(range.end))
+    }
+
+    fn text(input: &str, text: &str, range: Range<usize>) -> TurboInlineRaw {
+        TurboInlineRaw::Text {
+            text: text.to_string(),
+            span: span(input, range),
+        }
+    }
+
     #[test]
     fn parse() {
-        println!("{:?}", parser().parse(INPUT));
+        println!("{:?}", parser().parse(stream(INPUT)));
         // println!("{:?}", parser().parse("## Test\n"));
     }
 
+    #[test]
+    fn inline_math() {
+        let input = "costs \\$5, but $a_1 * b$ is free $\n";
+        let parse = parser().parse(stream(input)).unwrap();
+        assert_eq!(
+            parse,
+            Turbo::Root {
+                content: vec![Turbo::Line {
+                    ident: 0,
+                    text: vec![
+                        text(input, "costs ", 0..6),
+                        text(input, "$", 6..8),
+                        text(input, "5, but ", 8..15),
+                        TurboInlineRaw::Math {
+                            math: "a_1 * b".to_string(),
+                            span: span(input, 15..24),
+                        },
+                        text(input, " is free ", 24..33),
+                        text(input, "$", 33..34),
+                    ],
+                    span: span(input, 0..35),
+                }],
+                span: span(input, 0..35),
+            }
+        );
+    }
+
+    #[test]
+    fn image() {
+        let input = "wow! ![arch](img/arch.png \"Overview\"){width=300, height=50%}\n";
+        let (parse, errors) = parser().parse_recovery(stream(input));
+        assert!(errors.is_empty());
+        assert_eq!(
+            parse.unwrap(),
+            Turbo::Root {
+                content: vec![Turbo::Line {
+                    ident: 0,
+                    text: vec![
+                        text(input, "wow", 0..3),
+                        text(input, "!", 3..4),
+                        text(input, " ", 4..5),
+                        TurboInlineRaw::Image {
+                            image: Image {
+                                alt: Some("arch".to_string()),
+                                address: "img/arch.png".to_string(),
+                                title: Some("Overview".to_string()),
+                                width: Some("300".to_string()),
+                                height: Some("50%".to_string()),
+                            },
+                            span: span(input, 5..60),
+                        },
+                    ],
+                    span: span(input, 0..61),
+                }],
+                span: span(input, 0..61),
+            }
+        );
+    }
+
+    #[test]
+    fn image_without_alt() {
+        let (parse, errors) = parser().parse_recovery(stream("![](img/arch.png)\n"));
+        assert!(parse.is_some());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.range(), 0..17);
+    }
+
+    #[test]
+    fn table() {
+        let input = "| Name | *Value* |\n|:---|---:|\n| a | 1 |\n| b |\n";
+        let parse = parser().parse(stream(input)).unwrap();
+        let cell = |s: &str, range| TableCellRaw::new(vec![text(input, s, range)]);
+        assert_eq!(
+            parse,
+            Turbo::Root {
+                content: vec![Turbo::Table {
+                    ident: 0,
+                    header: vec![vec![
+                        cell("Name", 2..6),
+                        TableCellRaw::new(vec![
+                            TurboInlineRaw::ModFlag {
+                                kind: TurboTextMod::Bold,
+                                span: span(input, 9..10),
+                            },
+                            text(input, "Value", 10..15),
+                            TurboInlineRaw::ModFlag {
+                                kind: TurboTextMod::Bold,
+                                span: span(input, 15..16),
+                            },
+                        ]),
+                    ]],
+                    alignments: vec![Alignment::Left, Alignment::Right],
+                    rows: vec![
+                        vec![cell("a", 33..34), cell("1", 37..38)],
+                        vec![cell("b", 43..44), TableCellRaw::new(vec![])],
+                    ],
+                    span: span(input, 0..47),
+                }],
+                span: span(input, 0..47),
+            }
+        );
+    }
+
+    #[test]
+    fn table_extra_cells() {
+        let input = "| a | b |\n|---|---|\n| 1 | 2 | 3 | *4* |\n| 5 |\n";
+        let (parse, errors) = parser().parse_recovery(stream(input));
+        let Some([Turbo::Table { rows, .. }]) =
+            parse.as_ref().and_then(Turbo::root).map(Vec::as_slice)
+        else {
+            panic!("expected a table, got {parse:?}");
+        };
+        assert_eq!(rows.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ParseErrorKind::ExtraTableCells);
+        assert_eq!(errors[0].kind.severity(), Severity::Warning);
+        assert_eq!(&input[errors[0].span.range()], "3 | *4*");
+    }
+
+    #[test]
+    fn table_code_block() {
+        let input =
+            "::: table\n{\n  header: [[a, {text: b, colspan: 2}]],\n  rows: [[c, d, e]],\n}\n:::\n";
+        let (parse, errors) = parser().parse_recovery(stream(input));
+        assert!(errors.is_empty());
+        let cell = |s: &str, range| TableCellRaw::new(vec![text(input, s, range)]);
+        assert_eq!(
+            parse.unwrap(),
+            Turbo::Root {
+                content: vec![Turbo::Table {
+                    ident: 0,
+                    header: vec![vec![
+                        cell("a", 24..25),
+                        TableCellRaw {
+                            colspan: 2,
+                            ..cell("b", 34..35)
+                        }
+                    ]],
+                    alignments: vec![],
+                    rows: vec![vec![
+                        cell("c", 62..63),
+                        cell("d", 65..66),
+                        cell("e", 68..69)
+                    ]],
+                    span: span(input, 0..79),
+                }],
+                span: span(input, 0..79),
+            }
+        );
+    }
+
+    #[test]
+    fn table_code_block_error() {
+        let input = "::: table\n{\n  rows: [\n    [a, {txt: b}],\n  ],\n}\n:::\n";
+        let (parse, errors) = parser().parse_recovery(stream(input));
+        assert!(matches!(
+            parse.unwrap().root().unwrap()[..],
+            [Turbo::Code { .. }]
+        ));
+        assert_eq!(errors.len(), 1);
+        let start = errors[0].span.start;
+        assert_eq!(start.offset, input.find("{txt").unwrap());
+        assert_eq!((start.line, start.column), (4, 9));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let input = "a *b\nsee [x](y\nc # d\n::: rust\nfn main\n";
+        let (parse, errors) = parser().parse_recovery(stream(input));
+        let errors = errors
+            .into_iter()
+            .map(|error| (error.kind, error.span.range()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (ParseErrorKind::MalformedLink, 9..10),
+                (ParseErrorKind::UnterminatedBlock, 21..30),
+            ]
+        );
+        // the text of a malformed link is kept, so it doesn't fail the build
+        assert_eq!(ParseErrorKind::MalformedLink.severity(), Severity::Warning);
+        // nothing is dropped, the block runs to the end
+        assert!(matches!(
+            parse.unwrap().root().unwrap()[..],
+            [
+                Turbo::Line { .. },
+                Turbo::Line { .. },
+                Turbo::Line { .. },
+                Turbo::Code { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn unexpected_line_is_skipped() {
+        let (parse, errors) = parser().parse_recovery(stream("a\nb \\\n"));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].kind,
+            ParseErrorKind::Unexpected { found: None, .. }
+        ));
+        assert!(matches!(
+            parse.unwrap().root().unwrap()[..],
+            [Turbo::Line { .. }, Turbo::Empty { .. }]
+        ));
+    }
+
+    #[test]
+    fn quote() {
+        let input = "> # Title\n> > nested\n> -- Author\n";
+        let parse = parser().parse(stream(input)).unwrap();
+        assert_eq!(
+            parse,
+            Turbo::Root {
+                content: vec![Turbo::Quote {
+                    ident: 0,
+                    content: vec![
+                        Turbo::Header {
+                            ident: 0,
+                            size: 1,
+                            numbered: true,
+                            text: vec![text(input, "Title", 4..9)],
+                            span: span(input, 2..10),
+                        },
+                        Turbo::Quote {
+                            ident: 0,
+                            content: vec![Turbo::Line {
+                                ident: 0,
+                                text: vec![text(input, "nested", 14..20)],
+                                span: span(input, 14..21),
+                            }],
+                            attribution: None,
+                            span: span(input, 12..21),
+                        },
+                    ],
+                    attribution: Some(vec![text(input, "Author", 26..32)]),
+                    span: span(input, 0..33),
+                }],
+                span: span(input, 0..33),
+            }
+        );
+    }
+
+    #[test]
+    fn list_markers() {
+        let markers = parser()
+            .parse(stream(
+                "- 3. a\n- c. b\n- C. c\n- iv. d\n- XII. e\n- e.g. f\n- -2. g\n",
+            ))
+            .unwrap()
+            .root()
+            .unwrap()
+            .iter()
+            .map(|turbo| match turbo {
+                Turbo::ListElemStart { kind, number, .. } => (kind.clone(), *number),
+                _ => panic!("expected list element"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            markers,
+            vec![
+                (ListKind::Numbered, 3),
+                (ListKind::AlphabetLower, 3),
+                (ListKind::AlphabetUpper, 3),
+                (ListKind::RomanLower, 4),
+                (ListKind::RomanUpper, 12),
+                (ListKind::Unordered(None), 1),
+                (ListKind::Numbered, -2),
+            ]
+        );
+    }
+
+    #[test]
+    fn overflowing_list_number() {
+        let turbo = parser()
+            .parse(stream("- 99999999999999999999. x\n"))
+            .unwrap();
+        match turbo.root().unwrap().as_slice() {
+            [Turbo::ListElemStart { kind, number, .. }] => {
+                assert_eq!((kind.clone(), *number), (ListKind::Unordered(None), 1))
+            }
+            other => panic!("expected list element, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn custom_list_markers() {
+        let markers = parser()
+            .parse(stream(
+                "- {square} a\n- {\"(→)\"} b\n- {\"Step 3\"} c\n- [x] d\n- [e](f) g\n\
+                 - (optional) h\n- [^1] i\n",
+            ))
+            .unwrap()
+            .root()
+            .unwrap()
+            .iter()
+            .map(|turbo| match turbo {
+                Turbo::ListElemStart {
+                    kind,
+                    marker,
+                    check,
+                    ..
+                } => (kind.clone(), marker.clone(), *check),
+                _ => panic!("expected list element"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            markers,
+            vec![
+                (ListKind::Unordered(Some(3)), None, None),
+                (ListKind::Custom, Some("(→)".to_string()), None),
+                (ListKind::Custom, Some("Step 3".to_string()), None),
+                (ListKind::Unordered(None), None, Some(true)),
+                (ListKind::Unordered(None), None, None),
+                (ListKind::Unordered(None), None, None),
+                (ListKind::Unordered(None), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn footnote_at_item_start() {
+        let turbo = parser().parse(stream("- [^1] see note\n")).unwrap();
+        let Some([Turbo::ListElemStart { check, content, .. }]) = turbo.root().map(Vec::as_slice)
+        else {
+            panic!("expected list element");
+        };
+        // a reference, not a check
+        assert_eq!(*check, None);
+        let [Turbo::Line { text, .. }] = content.as_slice() else {
+            panic!("expected one line, got {content:?}");
+        };
+        match text.as_slice() {
+            [TurboInlineRaw::FootnoteRef { name, span }, TurboInlineRaw::Text { text, .. }] => {
+                assert_eq!(name, "1");
+                assert_eq!(span.range(), 2..6);
+                assert_eq!(text, " see note");
+            }
+            other => panic!("expected a footnote reference, got {other:?}"),
+        }
+    }
+
     #[test]
     fn test() {
         // let test = filter::<_, _, Simple<char>>(|c| *c != '\n' && *c != ']')
@@ -0,0 +1,536 @@
+use crate::ast::{Footnote, TableCell, TurboText, TurboTree};
+use crate::{
+    Alignment, Code, Error, FigureKind, Image, ListKind, StructureError, TocEntry, TurboTextMod,
+};
+
+/// An output format. The provided `render` methods walk the tree and call one
+/// callback per node kind, containers get one before and one after their
+/// content. Callbacks do nothing by default, so a format only implements the
+/// nodes it has output for, the content of the others is still rendered.
+///
+/// A renderer that returns an [`inner`](Renderer::inner) one passes every
+/// callback it doesn't implement on to it, so it can change how some nodes
+/// render and keep the rest of another format:
+///
+/// ```
+/// use turbo_md::{
+///     escape_attribute, escape_text, parse_string, Error, HtmlRenderer, Renderer, TurboTree,
+/// };
+///
+/// /// Links open in a new tab.
+/// struct NewTab(HtmlRenderer);
+///
+/// impl Renderer for NewTab {
+///     fn inner(&mut self) -> Option<&mut dyn Renderer> {
+///         Some(&mut self.0)
+///     }
+///
+///     fn link(&mut self, alias: Option<&str>, address: &str) -> Result<(), Error> {
+///         let html = format!(
+///             "<a href=\"{}\" target=\"_blank\">{}</a>",
+///             escape_attribute(address),
+///             escape_text(alias.unwrap_or(address)),
+///         );
+///         self.0.raw_html(&html)
+///     }
+/// }
+///
+/// let (parse, _) = parse_string("see [a & b](https://example.com)\n").unwrap();
+/// let tree = TurboTree::generate(parse).unwrap();
+/// let mut renderer = NewTab(HtmlRenderer::new(None));
+/// renderer.render(&tree).unwrap();
+/// assert_eq!(
+///     renderer.0.finish(),
+///     "<p>see <a href=\"https://example.com\" target=\"_blank\">a &amp; b</a></p>\n"
+/// );
+/// ```
+pub trait Renderer {
+    /// The renderer the callbacks that aren't implemented are passed to,
+    /// `None` by default.
+    fn inner(&mut self) -> Option<&mut dyn Renderer> {
+        None
+    }
+
+    fn document_start(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.document_start())
+    }
+    fn document_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.document_end())
+    }
+    fn paragraph_start(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.paragraph_start())
+    }
+    fn paragraph_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.paragraph_end())
+    }
+    /// `number` is the section number, if the heading is numbered.
+    fn heading_start(
+        &mut self,
+        size: usize,
+        id: &str,
+        number: Option<&str>,
+        text: &TurboText,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.heading_start(size, id, number, text))
+    }
+    fn heading_end(&mut self, size: usize, id: &str, text: &TurboText) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.heading_end(size, id, text))
+    }
+    fn list_start(&mut self, kind: &ListKind, start: isize) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.list_start(kind, start))
+    }
+    fn list_end(&mut self, kind: &ListKind, start: isize) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.list_end(kind, start))
+    }
+    fn list_item_start(
+        &mut self,
+        id: usize,
+        marker: Option<&str>,
+        check: Option<bool>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.list_item_start(id, marker, check))
+    }
+    fn list_item_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.list_item_end())
+    }
+    /// Around the label of a list item, a text or a heading, and its check
+    /// box. Only called if the item has either.
+    fn label_start(
+        &mut self,
+        id: usize,
+        check: Option<bool>,
+        label: Option<&TurboTree>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.label_start(id, check, label))
+    }
+    fn label_end(
+        &mut self,
+        id: usize,
+        check: Option<bool>,
+        label: Option<&TurboTree>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.label_end(id, check, label))
+    }
+    /// A code block, including raw HTML in [`Lang::Html`](crate::Lang::Html)
+    /// blocks, which formats other than HTML should leave out.
+    fn code(&mut self, code: &Code) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.code(code))
+    }
+    fn quote_start(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.quote_start())
+    }
+    fn quote_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.quote_end())
+    }
+    fn attribution_start(&mut self) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.attribution_start())
+    }
+    fn attribution_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.attribution_end())
+    }
+    fn table_start(&mut self, alignments: &[Alignment]) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.table_start(alignments))
+    }
+    fn table_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.table_end())
+    }
+    /// Around the header rows if `header`, the body rows otherwise. Tables
+    /// without header rows have no header section.
+    fn table_section_start(&mut self, header: bool) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.table_section_start(header))
+    }
+    fn table_section_end(&mut self, header: bool) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.table_section_end(header))
+    }
+    fn table_row_start(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.table_row_start())
+    }
+    fn table_row_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.table_row_end())
+    }
+    /// `alignment` is the one of the column the cell starts in, columns
+    /// covered by spans from other cells are skipped.
+    fn table_cell_start(
+        &mut self,
+        cell: &TableCell,
+        alignment: Alignment,
+        header: bool,
+    ) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| {
+            inner.table_cell_start(cell, alignment, header)
+        })
+    }
+    fn table_cell_end(&mut self, cell: &TableCell, header: bool) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.table_cell_end(cell, header))
+    }
+    fn footnotes_start(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.footnotes_start())
+    }
+    fn footnotes_end(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.footnotes_end())
+    }
+    fn footnote_start(&mut self, footnote: &Footnote) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.footnote_start(footnote))
+    }
+    fn footnote_end(&mut self, footnote: &Footnote) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.footnote_end(footnote))
+    }
+    fn horizontal(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.horizontal())
+    }
+    /// A table of contents, its entries are not rendered on their own.
+    fn toc(&mut self, entries: &[TocEntry]) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.toc(entries))
+    }
+    fn figure_start(
+        &mut self,
+        kind: FigureKind,
+        id: Option<&str>,
+        number: Option<usize>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.figure_start(kind, id, number))
+    }
+    fn figure_end(
+        &mut self,
+        kind: FigureKind,
+        id: Option<&str>,
+        number: Option<usize>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.figure_end(kind, id, number))
+    }
+    /// Around the caption after the content of a figure. Also called for
+    /// figures with only a label, so the number can be shown.
+    fn caption_start(
+        &mut self,
+        kind: FigureKind,
+        number: Option<usize>,
+        caption: Option<&TurboText>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.caption_start(kind, number, caption))
+    }
+    fn caption_end(
+        &mut self,
+        kind: FigureKind,
+        number: Option<usize>,
+        caption: Option<&TurboText>,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.caption_end(kind, number, caption))
+    }
+
+    fn modifier_start(&mut self, kind: TurboTextMod) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.modifier_start(kind))
+    }
+    fn modifier_end(&mut self, kind: TurboTextMod) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.modifier_end(kind))
+    }
+    fn link(&mut self, alias: Option<&str>, address: &str) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.link(alias, address))
+    }
+    fn image(&mut self, image: &Image) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.image(image))
+    }
+    /// `number` is `None` for references to footnotes that are not defined.
+    fn footnote_ref(
+        &mut self,
+        name: &str,
+        number: Option<usize>,
+        occurrence: usize,
+    ) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.footnote_ref(name, number, occurrence))
+    }
+    fn plain(&mut self, text: &str) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.plain(text))
+    }
+    fn math(&mut self, math: &str) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.math(math))
+    }
+    /// `number` is `None` for references to labels no figure has.
+    fn figure_ref(&mut self, id: &str, number: Option<(FigureKind, usize)>) -> Result<(), Error> {
+        self.inner()
+            .map_or(Ok(()), |inner| inner.figure_ref(id, number))
+    }
+    /// Raw inline HTML, formats other than HTML should leave it out.
+    fn raw_html(&mut self, html: &str) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.raw_html(html))
+    }
+    fn line_break(&mut self) -> Result<(), Error> {
+        self.inner().map_or(Ok(()), |inner| inner.line_break())
+    }
+
+    /// Renders a `Root` as a whole document.
+    fn render(&mut self, root: &TurboTree) -> Result<(), Error> {
+        let TurboTree::Root { content, .. } = root else {
+            return Err(StructureError::NotRoot.into());
+        };
+        self.document_start()?;
+        for node in content {
+            self.render_tree(node)?;
+        }
+        self.document_end()
+    }
+
+    fn render_tree(&mut self, node: &TurboTree) -> Result<(), Error> {
+        match node {
+            TurboTree::Root { .. } => return Err(StructureError::NestedRoot.into()),
+            TurboTree::Text { text, .. } => {
+                self.paragraph_start()?;
+                self.render_text(text)?;
+                self.paragraph_end()?;
+            }
+            TurboTree::Heading {
+                size,
+                text,
+                id,
+                number,
+                ..
+            } => self.render_heading(*size, id, number.as_deref(), text)?,
+            TurboTree::List {
+                kind, start, items, ..
+            } => {
+                self.list_start(kind, *start)?;
+                for node in items {
+                    self.render_tree(node)?;
+                }
+                self.list_end(kind, *start)?;
+            }
+            TurboTree::ListItem {
+                id,
+                marker,
+                check,
+                label,
+                items,
+                ..
+            } => {
+                self.list_item_start(*id, marker.as_deref(), *check)?;
+                let label = label.as_ref().as_ref();
+                if label.is_some() || check.is_some() {
+                    self.label_start(*id, *check, label)?;
+                    match label {
+                        Some(TurboTree::Text { text, .. }) => self.render_text(text)?,
+                        Some(TurboTree::Heading {
+                            size,
+                            text,
+                            id,
+                            number,
+                            ..
+                        }) => self.render_heading(*size, id, number.as_deref(), text)?,
+                        Some(_) => return Err(StructureError::InvalidListLabel.into()),
+                        None => {}
+                    }
+                    self.label_end(*id, *check, label)?;
+                }
+                for node in items {
+                    self.render_tree(node)?;
+                }
+                self.list_item_end()?;
+            }
+            TurboTree::Code { code, .. } => self.code(code)?,
+            TurboTree::Quote {
+                content,
+                attribution,
+                ..
+            } => {
+                self.quote_start()?;
+                for node in content {
+                    self.render_tree(node)?;
+                }
+                if let Some(attribution) = attribution {
+                    self.attribution_start()?;
+                    self.render_text(attribution)?;
+                    self.attribution_end()?;
+                }
+                self.quote_end()?;
+            }
+            TurboTree::Table {
+                header,
+                alignments,
+                rows,
+                ..
+            } => {
+                self.table_start(alignments)?;
+                if !header.is_empty() {
+                    self.render_table_section(header, alignments, true)?;
+                }
+                self.render_table_section(rows, alignments, false)?;
+                self.table_end()?;
+            }
+            TurboTree::FootnoteDef { .. } => {}
+            TurboTree::Footnotes { footnotes, .. } => {
+                self.footnotes_start()?;
+                for footnote in footnotes {
+                    self.footnote_start(footnote)?;
+                    self.render_text(&footnote.text)?;
+                    self.footnote_end(footnote)?;
+                }
+                self.footnotes_end()?;
+            }
+            TurboTree::Horizontal { .. } => self.horizontal()?,
+            TurboTree::Toc { entries, .. } => self.toc(entries)?,
+            TurboTree::Figure {
+                kind,
+                id,
+                number,
+                caption,
+                content,
+                ..
+            } => {
+                self.figure_start(*kind, id.as_deref(), *number)?;
+                for node in content {
+                    self.render_tree(node)?;
+                }
+                self.caption_start(*kind, *number, caption.as_ref())?;
+                if let Some(caption) = caption {
+                    self.render_text(caption)?;
+                }
+                self.caption_end(*kind, *number, caption.as_ref())?;
+                self.figure_end(*kind, id.as_deref(), *number)?;
+            }
+            TurboTree::Empty { .. } => {}
+            TurboTree::Include { path, .. } => {
+                return Err(StructureError::UnresolvedInclude(path.clone()).into())
+            }
+        }
+        Ok(())
+    }
+
+    fn render_heading(
+        &mut self,
+        size: usize,
+        id: &str,
+        number: Option<&str>,
+        text: &TurboText,
+    ) -> Result<(), Error> {
+        self.heading_start(size, id, number, text)?;
+        self.render_text(text)?;
+        self.heading_end(size, id, text)
+    }
+
+    fn render_table_section(
+        &mut self,
+        rows: &[Vec<TableCell>],
+        alignments: &[Alignment],
+        header: bool,
+    ) -> Result<(), Error> {
+        self.table_section_start(header)?;
+        // remaining rows each column is still covered by a rowspan from above
+        let mut occupied: Vec<usize> = vec![];
+        for row in rows {
+            self.table_row_start()?;
+            let mut column = 0;
+            for cell in row {
+                while occupied.get(column).copied().unwrap_or(0) > 0 {
+                    column += 1;
+                }
+                let alignment = alignments
+                    .get(column)
+                    .copied()
+                    .unwrap_or(Alignment::Default);
+                self.table_cell_start(cell, alignment, header)?;
+                self.render_text(&cell.text)?;
+                self.table_cell_end(cell, header)?;
+
+                if occupied.len() < column + cell.colspan {
+                    occupied.resize(column + cell.colspan, 0);
+                }
+                occupied[column..column + cell.colspan].fill(cell.rowspan);
+                column += cell.colspan;
+            }
+            self.table_row_end()?;
+            occupied
+                .iter_mut()
+                .for_each(|rows| *rows = rows.saturating_sub(1));
+        }
+        self.table_section_end(header)
+    }
+
+    fn render_text(&mut self, text: &TurboText) -> Result<(), Error> {
+        match text {
+            TurboText::TextContainer { text, .. } => {
+                for node in text {
+                    self.render_text(node)?;
+                }
+                Ok(())
+            }
+            TurboText::Mod { kind, text, .. } => {
+                self.modifier_start(*kind)?;
+                for node in text {
+                    self.render_text(node)?;
+                }
+                self.modifier_end(*kind)
+            }
+            TurboText::Link { alias, address, .. } => self.link(alias.as_deref(), address),
+            TurboText::Image { image, .. } => self.image(image),
+            TurboText::FootnoteRef {
+                name,
+                number,
+                occurrence,
+                ..
+            } => self.footnote_ref(name, *number, *occurrence),
+            TurboText::FigureRef { id, number, .. } => self.figure_ref(id, *number),
+            TurboText::Plain { text, .. } => self.plain(text),
+            TurboText::Math { math, .. } => self.math(math),
+            TurboText::Html { html, .. } => self.raw_html(html),
+            TurboText::NewLine { .. } => self.line_break(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+    use crate::parse_string;
+
+    /// Only the text, one paragraph per line.
+    #[derive(Default)]
+    struct PlainText(String);
+
+    impl Renderer for PlainText {
+        fn paragraph_end(&mut self) -> Result<(), Error> {
+            self.0.push('\n');
+            Ok(())
+        }
+        fn plain(&mut self, text: &str) -> Result<(), Error> {
+            self.0.push_str(text);
+            Ok(())
+        }
+        fn line_break(&mut self) -> Result<(), Error> {
+            self.0.push(' ');
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_renderer() {
+        let (parse, _) = parse_string("a *b* \\html{<br>}\n> c\n").unwrap();
+        let tree = TurboTree::generate(parse).unwrap();
+        let mut renderer = PlainText::default();
+        renderer.render(&tree).unwrap();
+        assert_eq!(renderer.0, "a b \nc\n");
+        assert!(matches!(
+            renderer.render_tree(&tree),
+            Err(Error::Structure(StructureError::NestedRoot))
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+
+/// Restrictions for rendering untrusted documents. Links and images may only
+/// use `http`, `https` and `mailto` or be relative, others are kept as text.
+/// Raw HTML is escaped like any other text.
+#[derive(Debug, Clone)]
+pub struct SafeMode {
+    /// Directory includes have to stay in, the directory of the document if `None`.
+    pub include_root: Option<PathBuf>,
+    /// Largest accepted input in bytes, a document counts with all of its
+    /// includes, each time they are included.
+    pub max_input: usize,
+    /// Deepest accepted nesting of quotes and lists.
+    pub max_depth: usize,
+}
+
+impl Default for SafeMode {
+    fn default() -> Self {
+        Self {
+            include_root: None,
+            max_input: 1 << 20,
+            max_depth: 32,
+        }
+    }
+}
+
+/// Whether the address is relative or uses an allowed scheme.
+pub(crate) fn is_safe_address(address: &str) -> bool {
+    // browsers ignore these inside of a scheme, `java\tscript:` is still javascript
+    let address = address
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>();
+    let scheme = match address.split_once(':') {
+        Some((scheme, _))
+            if scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) =>
+        {
+            scheme.to_ascii_lowercase()
+        }
+        // a `:` after a `/`, `?` or `#` is part of a relative address
+        _ => return true,
+    };
+    matches!(scheme.as_str(), "http" | "https" | "mailto")
+}
+
+#[cfg(test)]
+mod safe_tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::{parse_with_options, Diagnostic, Error, Options, ParseErrorKind, TurboTree};
+
+    fn generate(input: &str, options: &Options) -> Result<(String, Vec<Diagnostic>), Error> {
+        let (parse, _) = parse_with_options(input, options)?;
+        let (tree, diagnostics) = TurboTree::generate_with_options(parse, options)?;
+        Ok((tree.generate_html(None)?, diagnostics))
+    }
+
+    #[test]
+    fn addresses() {
+        for safe in [
+            "setup.tmd#install",
+            "/docs",
+            "https://x.org/a:b",
+            "MailTo:a@b.c",
+            "a/b:c",
+        ] {
+            assert!(is_safe_address(safe), "{safe}");
+        }
+        for unsafe_ in [
+            "javascript:alert(1)",
+            " JavaScript:x",
+            "java\tscript:x",
+            "data:text/html,x",
+            "vbscript:x",
+        ] {
+            assert!(!is_safe_address(unsafe_), "{unsafe_}");
+        }
+    }
+
+    #[test]
+    fn removes_unsafe_addresses() {
+        let input = "[x](javascript:void) ![pic](data:image/png,x) [y](https://y.org)\n";
+        let (html, diagnostics) = generate(input, &Options::safe()).unwrap();
+        assert_eq!(html, "<p>x pic <a href=\"https://y.org\">y</a></p>\n");
+        assert!(matches!(
+            &diagnostics[..],
+            [
+                Diagnostic::Parse { error: first, .. },
+                Diagnostic::Parse { error: second, .. },
+            ] if first.kind == ParseErrorKind::UnsafeAddress("javascript:void".to_string())
+                && second.kind == ParseErrorKind::UnsafeAddress("data:image/png,x".to_string())
+        ));
+        // kept without the safe mode
+        let (html, _) = generate(input, &Options::default()).unwrap();
+        assert!(html.contains("href=\"javascript:void\""));
+    }
+
+    #[test]
+    fn escapes_raw_html() {
+        let input = "::: html\n<script></script>\n:::\n\\html{<b>}\n";
+        let (html, diagnostics) = generate(input, &Options::safe()).unwrap();
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-html\">\n&lt;script&gt;&lt;/script&gt;\n</code></pre>\n<p>&lt;b&gt;</p>\n"
+        );
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn limits() {
+        let options = Options {
+            safe: Some(SafeMode {
+                max_input: 32,
+                max_depth: 2,
+                ..SafeMode::default()
+            }),
+            ..Options::default()
+        };
+        assert!(generate("> > a\n", &options).is_ok());
+        assert!(matches!(
+            generate("> > > a\n", &options),
+            Err(Error::NestingTooDeep { max: 2, .. })
+        ));
+        assert!(matches!(
+            generate("- a\n  - b\n    - c\n", &options),
+            Err(Error::NestingTooDeep { max: 2, .. })
+        ));
+        assert!(matches!(
+            generate(&"a".repeat(33), &options),
+            Err(Error::InputTooLarge { size: 33, .. })
+        ));
+    }
+
+    #[test]
+    fn includes_stay_in_root() {
+        let dir = TempDir::new(
+            "safe",
+            &[
+                ("docs/main.tmd", "@[../secret]\n"),
+                ("secret.tmd", "secret\n"),
+            ],
+        );
+
+        let main = dir.join("docs/main.tmd");
+        assert!(TurboTree::generate_file(&main).is_ok());
+        assert!(matches!(
+            TurboTree::generate_file_with_options(&main, &Options::safe()),
+            Err(Error::IncludeOutsideRoot { path, .. }) if path == dir.join("docs/../secret.tmd")
+        ));
+        let options = Options {
+            safe: Some(SafeMode {
+                include_root: Some(dir.path().to_path_buf()),
+                ..SafeMode::default()
+            }),
+            ..Options::default()
+        };
+        assert!(TurboTree::generate_file_with_options(&main, &options).is_ok());
+    }
+
+    #[test]
+    fn limits_includes() {
+        // every file includes the next one twice, 2^12 copies of the last one
+        let mut files = (0..12)
+            .map(|i| (format!("{i}.tmd"), format!("@[{0}]\n@[{0}]\n", i + 1)))
+            .collect::<Vec<_>>();
+        files.push(("12.tmd".to_string(), "leaf\n".to_string()));
+        let files = files
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_str()))
+            .collect::<Vec<_>>();
+        let dir = TempDir::new("safe-fan", &files);
+
+        let main = dir.join("0.tmd");
+        let options = Options {
+            safe: Some(SafeMode {
+                max_input: 1024,
+                ..SafeMode::default()
+            }),
+            ..Options::default()
+        };
+        assert!(matches!(
+            TurboTree::generate_file_with_options(&main, &options),
+            Err(Error::InputTooLarge { file: Some(file), size: 1025.., max: 1024 }) if file == main
+        ));
+        assert!(TurboTree::generate_file_with_options(dir.join("9.tmd"), &options).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use chumsky::Stream;
+use std::fmt;
+use std::ops::Range;
+
+/// A location in the source. `offset` is in bytes, `line` and `column` start at 1
+/// and the column counts characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// The position after `c`.
+    pub fn advance(self, c: char) -> Self {
+        if c == '\n' {
+            Self {
+                offset: self.offset + 1,
+                line: self.line + 1,
+                column: 1,
+            }
+        } else {
+            Self {
+                offset: self.offset + c.len_utf8(),
+                column: self.column + 1,
+                ..self
+            }
+        }
+    }
+}
+
+/// The source range a node was parsed from, relative to its own file.
+/// Nodes that don't appear in the source, like the footnote section, have an
+/// empty span at the start of the file.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+    /// index of the file in the order the resolver reads them, 0 for the
+    /// entry file and parsed strings
+    pub(crate) file: usize,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            file: 0,
+        }
+    }
+
+    /// Byte range in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    /// From the start of `self` to the end of `other`.
+    pub fn join(self, other: Span) -> Self {
+        Self {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start.line, self.start.column)
+    }
+}
+
+// kept short, spans are on every node of the `Debug` output
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {}:{}-{}:{}",
+            self.range(),
+            self.start.line,
+            self.start.column,
+            self.end.line,
+            self.end.column
+        )
+    }
+}
+
+impl chumsky::Span for Span {
+    type Context = ();
+    type Offset = Position;
+
+    fn new((): Self::Context, range: Range<Position>) -> Self {
+        Span::new(range.start, range.end)
+    }
+
+    fn context(&self) -> Self::Context {}
+
+    fn start(&self) -> Self::Offset {
+        self.start
+    }
+
+    fn end(&self) -> Self::Offset {
+        self.end
+    }
+}
+
+/// The characters of `content` from `start` on, each with its span.
+pub(crate) fn spanned_chars(
+    content: impl IntoIterator<Item = char>,
+    start: Position,
+) -> impl Iterator<Item = (char, Span)> {
+    content.into_iter().scan(start, |position, c| {
+        let start = *position;
+        *position = start.advance(c);
+        Some((c, Span::new(start, *position)))
+    })
+}
+
+/// Parser input for `content`, spans are counted from the start of the file.
+pub(crate) fn stream(
+    content: &str,
+) -> Stream<'_, char, Span, impl Iterator<Item = (char, Span)> + '_> {
+    let end = content
+        .chars()
+        .fold(Position::default(), |position, c| position.advance(c));
+    Stream::from_iter(
+        Span::new(end, end),
+        spanned_chars(content.chars(), Position::default()),
+    )
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A directory of files for a test, removed again when it is dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates the directory with `files`, paths relative to it and their
+    /// content. `name` has to be unique among the tests.
+    pub(crate) fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        // unique per process, so parallel test runs don't share files
+        let dir = std::env::temp_dir().join(format!("turbo-{name}-{}", std::process::id()));
+        // left over from a run that didn't finish
+        let _ = fs::remove_dir_all(&dir);
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub(crate) fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
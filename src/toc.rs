@@ -0,0 +1,200 @@
+use crate::ast::{TurboText, TurboTree};
+use crate::span::Span;
+use crate::visit::{walk_tree, walk_tree_mut, Visitor, VisitorMut};
+
+/// Which headings a table of contents lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TocOptions {
+    /// Size of the largest headings listed, `#` is 1.
+    pub min_depth: usize,
+    /// Size of the smallest headings listed.
+    pub max_depth: usize,
+    /// Puts a table of contents at the start of documents without `@[toc]`.
+    pub prepend: bool,
+}
+
+impl Default for TocOptions {
+    fn default() -> Self {
+        Self {
+            min_depth: 1,
+            max_depth: 6,
+            prepend: false,
+        }
+    }
+}
+
+/// A heading in the table of contents, with the smaller headings following
+/// it up to the next one of its size or larger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    pub size: usize,
+    /// Anchor id of the heading.
+    pub id: String,
+    /// Section number of the heading, if numbered.
+    pub number: Option<String>,
+    pub text: TurboText,
+    pub children: Vec<TocEntry>,
+}
+
+impl TurboTree {
+    /// The table of contents of a `Root`, empty for other nodes. Headings in
+    /// list items are listed like the others, headings in quotes are left out.
+    pub fn toc(&self, options: &TocOptions) -> Vec<TocEntry> {
+        match self {
+            TurboTree::Root { content, .. } => toc(content, options),
+            _ => vec![],
+        }
+    }
+}
+
+pub(crate) fn toc(content: &[TurboTree], options: &TocOptions) -> Vec<TocEntry> {
+    let mut headings = Headings {
+        options,
+        entries: vec![],
+    };
+    content.iter().for_each(|node| headings.visit_tree(node));
+    headings.entries
+}
+
+/// Fills every `@[toc]` of the content, or puts one at the start if there is
+/// none and [`TocOptions::prepend`] is set.
+pub(crate) fn insert(content: &mut Vec<TurboTree>, options: &TocOptions) {
+    let entries = toc(content, options);
+    let mut fill = Fill {
+        entries: &entries,
+        filled: false,
+    };
+    content
+        .iter_mut()
+        .for_each(|node| fill.visit_tree_mut(node));
+    if !fill.filled && options.prepend {
+        content.insert(
+            0,
+            TurboTree::Toc {
+                entries,
+                span: Span::default(),
+            },
+        );
+    }
+}
+
+struct Headings<'a> {
+    options: &'a TocOptions,
+    entries: Vec<TocEntry>,
+}
+
+impl Visitor for Headings<'_> {
+    fn visit_tree(&mut self, node: &TurboTree) {
+        match node {
+            TurboTree::Heading {
+                size,
+                text,
+                id,
+                number,
+                ..
+            } if (self.options.min_depth..=self.options.max_depth).contains(size) => {
+                let entry = TocEntry {
+                    size: *size,
+                    id: id.clone(),
+                    number: number.clone(),
+                    text: text.clone(),
+                    children: vec![],
+                };
+                nest(&mut self.entries, entry)
+            }
+            TurboTree::Quote { .. } => {}
+            node => walk_tree(self, node),
+        }
+    }
+
+    fn visit_text(&mut self, _text: &TurboText) {}
+}
+
+/// Appends `entry` to the last entry of `siblings` while that one is larger.
+fn nest(siblings: &mut Vec<TocEntry>, entry: TocEntry) {
+    match siblings.last_mut() {
+        Some(last) if last.size < entry.size => nest(&mut last.children, entry),
+        _ => siblings.push(entry),
+    }
+}
+
+struct Fill<'a> {
+    entries: &'a [TocEntry],
+    filled: bool,
+}
+
+impl VisitorMut for Fill<'_> {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        if let TurboTree::Toc { entries, .. } = node {
+            *entries = self.entries.to_vec();
+            self.filled = true;
+        }
+        walk_tree_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, _text: &mut TurboText) {}
+}
+
+#[cfg(test)]
+mod toc_tests {
+    use super::*;
+    use crate::{parse_string, Options};
+
+    fn generate(input: &str, toc: TocOptions) -> TurboTree {
+        let options = Options {
+            toc,
+            ..Options::default()
+        };
+        let (parse, _) = parse_string(input).unwrap();
+        TurboTree::generate_with_options(parse, &options).unwrap().0
+    }
+
+    #[test]
+    fn nesting_and_depth() {
+        let input = "# A\n### B\n## C\n> # quoted\n- item\n  ## D\n# E\n";
+        let tree = generate(input, TocOptions::default());
+        let sizes = |entries: &[TocEntry]| {
+            entries
+                .iter()
+                .map(|entry| (entry.id.clone(), entry.children.len()))
+                .collect::<Vec<_>>()
+        };
+        let toc = tree.toc(&TocOptions::default());
+        assert_eq!(sizes(&toc), [("a".to_string(), 3), ("e".to_string(), 0)]);
+        assert_eq!(
+            sizes(&toc[0].children),
+            [
+                ("b".to_string(), 0),
+                ("c".to_string(), 0),
+                ("d".to_string(), 0)
+            ]
+        );
+
+        let options = TocOptions {
+            min_depth: 2,
+            max_depth: 2,
+            ..TocOptions::default()
+        };
+        assert_eq!(
+            sizes(&tree.toc(&options)),
+            [("c".to_string(), 0), ("d".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn html() {
+        let html = |input, toc| generate(input, toc).generate_html(None).unwrap();
+        let toc = "<nav class=\"toc\">\n<ul>\n<li><a href=\"#a\">A</a>\n\
+                   <ul>\n<li><a href=\"#b-b\">B b</a></li>\n</ul>\n</li>\n</ul>\n</nav>\n";
+        assert_eq!(
+            html("@[toc]\n# A\n## B **b**\n", TocOptions::default()),
+            format!("{toc}<h1 id=\"a\">A</h1>\n<h2 id=\"b-b\">B <i>b</i></h2>\n")
+        );
+        let prepend = TocOptions {
+            prepend: true,
+            ..TocOptions::default()
+        };
+        assert!(html("# A\n## B **b**\n", prepend).starts_with(toc));
+        assert!(html("# A\n", TocOptions::default()).starts_with("<h1"));
+    }
+}
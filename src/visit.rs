@@ -0,0 +1,467 @@
+//! Traversals of the generated tree. [`Visitor`] and [`VisitorMut`] walk
+//! every node in document order, [`Fold`] rebuilds the tree and can replace
+//! or remove nodes. Each method defaults to the matching `walk_*` or `fold_*`
+//! function, which an override calls to carry on into the children. A whole
+//! document is folded with [`fold_tree_children`] on its root.
+//!
+//! ```
+//! use turbo_md::visit::{walk_text, walk_text_mut, Visitor, VisitorMut};
+//! use turbo_md::{parse_string, TurboText, TurboTree};
+//!
+//! /// Collects the addresses of all links.
+//! struct Links(Vec<String>);
+//!
+//! impl Visitor for Links {
+//!     fn visit_text(&mut self, text: &TurboText) {
+//!         if let TurboText::Link { address, .. } = text {
+//!             self.0.push(address.clone());
+//!         }
+//!         walk_text(self, text)
+//!     }
+//! }
+//!
+//! /// Upgrades `http` links.
+//! struct Https;
+//!
+//! impl VisitorMut for Https {
+//!     fn visit_text_mut(&mut self, text: &mut TurboText) {
+//!         if let TurboText::Link { address, .. } = text {
+//!             if let Some(rest) = address.strip_prefix("http://") {
+//!                 *address = format!("https://{rest}");
+//!             }
+//!         }
+//!         walk_text_mut(self, text)
+//!     }
+//! }
+//!
+//! let (parse, _) = parse_string("*see [a](http://a.org)* and [b](b.tmd)\n").unwrap();
+//! let mut tree = TurboTree::generate(parse).unwrap();
+//! Https.visit_tree_mut(&mut tree);
+//! let mut links = Links(vec![]);
+//! links.visit_tree(&tree);
+//! assert_eq!(links.0, ["https://a.org", "b.tmd"]);
+//! ```
+
+use crate::ast::{TableCell, TurboText, TurboTree};
+use crate::TocEntry;
+
+pub trait Visitor {
+    fn visit_tree(&mut self, node: &TurboTree) {
+        walk_tree(self, node)
+    }
+
+    fn visit_text(&mut self, text: &TurboText) {
+        walk_text(self, text)
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_tree_mut(&mut self, node: &mut TurboTree) {
+        walk_tree_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, text: &mut TurboText) {
+        walk_text_mut(self, text)
+    }
+}
+
+pub trait Fold {
+    /// The nodes that take the place of `node`, none removes it.
+    fn fold_tree(&mut self, node: TurboTree) -> Vec<TurboTree> {
+        vec![fold_tree_children(self, node)]
+    }
+
+    fn fold_text(&mut self, text: TurboText) -> TurboText {
+        fold_text_children(self, text)
+    }
+}
+
+/// Visits the children of `node`: content, the label before the items of a
+/// list item, the attribution after the content of a quote and the caption
+/// after the content of a figure, table cells row by row and the text of
+/// footnotes and table of contents entries.
+pub fn walk_tree<V: Visitor + ?Sized>(visitor: &mut V, node: &TurboTree) {
+    match node {
+        TurboTree::Root { content, .. } => content.iter().for_each(|node| visitor.visit_tree(node)),
+        TurboTree::Quote {
+            content,
+            attribution,
+            ..
+        } => {
+            content.iter().for_each(|node| visitor.visit_tree(node));
+            if let Some(attribution) = attribution {
+                visitor.visit_text(attribution);
+            }
+        }
+        TurboTree::Text { text, .. }
+        | TurboTree::Heading { text, .. }
+        | TurboTree::FootnoteDef { text, .. } => visitor.visit_text(text),
+        TurboTree::List { items, .. } => items.iter().for_each(|node| visitor.visit_tree(node)),
+        TurboTree::ListItem { label, items, .. } => {
+            if let Some(label) = label.as_ref() {
+                visitor.visit_tree(label);
+            }
+            items.iter().for_each(|node| visitor.visit_tree(node));
+        }
+        TurboTree::Table { header, rows, .. } => header
+            .iter()
+            .chain(rows)
+            .flatten()
+            .for_each(|cell| visitor.visit_text(&cell.text)),
+        TurboTree::Footnotes { footnotes, .. } => footnotes
+            .iter()
+            .for_each(|footnote| visitor.visit_text(&footnote.text)),
+        TurboTree::Toc { entries, .. } => walk_toc(visitor, entries),
+        TurboTree::Figure {
+            caption, content, ..
+        } => {
+            content.iter().for_each(|node| visitor.visit_tree(node));
+            if let Some(caption) = caption {
+                visitor.visit_text(caption);
+            }
+        }
+        TurboTree::Code { .. }
+        | TurboTree::Horizontal { .. }
+        | TurboTree::Empty { .. }
+        | TurboTree::Include { .. } => {}
+    }
+}
+
+fn walk_toc<V: Visitor + ?Sized>(visitor: &mut V, entries: &[TocEntry]) {
+    for entry in entries {
+        visitor.visit_text(&entry.text);
+        walk_toc(visitor, &entry.children);
+    }
+}
+
+/// Visits the children of containers and modifiers.
+pub fn walk_text<V: Visitor + ?Sized>(visitor: &mut V, text: &TurboText) {
+    if let TurboText::TextContainer { text, .. } | TurboText::Mod { text, .. } = text {
+        text.iter().for_each(|node| visitor.visit_text(node));
+    }
+}
+
+/// Like [`walk_tree`].
+pub fn walk_tree_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut TurboTree) {
+    match node {
+        TurboTree::Root { content, .. } => content
+            .iter_mut()
+            .for_each(|node| visitor.visit_tree_mut(node)),
+        TurboTree::Quote {
+            content,
+            attribution,
+            ..
+        } => {
+            content
+                .iter_mut()
+                .for_each(|node| visitor.visit_tree_mut(node));
+            if let Some(attribution) = attribution {
+                visitor.visit_text_mut(attribution);
+            }
+        }
+        TurboTree::Text { text, .. }
+        | TurboTree::Heading { text, .. }
+        | TurboTree::FootnoteDef { text, .. } => visitor.visit_text_mut(text),
+        TurboTree::List { items, .. } => items
+            .iter_mut()
+            .for_each(|node| visitor.visit_tree_mut(node)),
+        TurboTree::ListItem { label, items, .. } => {
+            if let Some(label) = label.as_mut() {
+                visitor.visit_tree_mut(label);
+            }
+            items
+                .iter_mut()
+                .for_each(|node| visitor.visit_tree_mut(node));
+        }
+        TurboTree::Table { header, rows, .. } => header
+            .iter_mut()
+            .chain(rows)
+            .flatten()
+            .for_each(|cell| visitor.visit_text_mut(&mut cell.text)),
+        TurboTree::Footnotes { footnotes, .. } => footnotes
+            .iter_mut()
+            .for_each(|footnote| visitor.visit_text_mut(&mut footnote.text)),
+        TurboTree::Toc { entries, .. } => walk_toc_mut(visitor, entries),
+        TurboTree::Figure {
+            caption, content, ..
+        } => {
+            content
+                .iter_mut()
+                .for_each(|node| visitor.visit_tree_mut(node));
+            if let Some(caption) = caption {
+                visitor.visit_text_mut(caption);
+            }
+        }
+        TurboTree::Code { .. }
+        | TurboTree::Horizontal { .. }
+        | TurboTree::Empty { .. }
+        | TurboTree::Include { .. } => {}
+    }
+}
+
+fn walk_toc_mut<V: VisitorMut + ?Sized>(visitor: &mut V, entries: &mut [TocEntry]) {
+    for entry in entries {
+        visitor.visit_text_mut(&mut entry.text);
+        walk_toc_mut(visitor, &mut entry.children);
+    }
+}
+
+/// Like [`walk_text`].
+pub fn walk_text_mut<V: VisitorMut + ?Sized>(visitor: &mut V, text: &mut TurboText) {
+    if let TurboText::TextContainer { text, .. } | TurboText::Mod { text, .. } = text {
+        text.iter_mut()
+            .for_each(|node| visitor.visit_text_mut(node));
+    }
+}
+
+/// Folds every node of `content` in order.
+pub fn fold_content<F: Fold + ?Sized>(folder: &mut F, content: Vec<TurboTree>) -> Vec<TurboTree> {
+    content
+        .into_iter()
+        .flat_map(|node| folder.fold_tree(node))
+        .collect()
+}
+
+/// Folds the children of `node` in the order of [`walk_tree`]. A list item
+/// label that is folded into several nodes keeps the first one, the others
+/// are moved in front of the items.
+pub fn fold_tree_children<F: Fold + ?Sized>(folder: &mut F, node: TurboTree) -> TurboTree {
+    match node {
+        TurboTree::Root { content, span } => TurboTree::Root {
+            content: fold_content(folder, content),
+            span,
+        },
+        TurboTree::Quote {
+            content,
+            attribution,
+            span,
+        } => TurboTree::Quote {
+            content: fold_content(folder, content),
+            attribution: attribution.map(|text| folder.fold_text(text)),
+            span,
+        },
+        TurboTree::Text { text, span } => TurboTree::Text {
+            text: folder.fold_text(text),
+            span,
+        },
+        TurboTree::Heading {
+            size,
+            text,
+            id,
+            numbered,
+            number,
+            span,
+        } => TurboTree::Heading {
+            size,
+            text: folder.fold_text(text),
+            id,
+            numbered,
+            number,
+            span,
+        },
+        TurboTree::FootnoteDef { name, text, span } => TurboTree::FootnoteDef {
+            name,
+            text: folder.fold_text(text),
+            span,
+        },
+        TurboTree::List {
+            kind,
+            start,
+            items,
+            span,
+        } => TurboTree::List {
+            kind,
+            start,
+            items: fold_content(folder, items),
+            span,
+        },
+        TurboTree::ListItem {
+            id,
+            marker,
+            check,
+            label,
+            items,
+            span,
+        } => {
+            let mut labels = match *label {
+                Some(label) => folder.fold_tree(label).into_iter(),
+                None => vec![].into_iter(),
+            };
+            let label = labels.next();
+            let items = labels.chain(fold_content(folder, items)).collect();
+            TurboTree::ListItem {
+                id,
+                marker,
+                check,
+                label: Box::new(label),
+                items,
+                span,
+            }
+        }
+        TurboTree::Table {
+            header,
+            alignments,
+            rows,
+            span,
+        } => {
+            let mut fold_rows = |rows: Vec<Vec<TableCell>>| {
+                rows.into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .map(|cell| TableCell {
+                                text: folder.fold_text(cell.text),
+                                ..cell
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            TurboTree::Table {
+                header: fold_rows(header),
+                alignments,
+                rows: fold_rows(rows),
+                span,
+            }
+        }
+        TurboTree::Footnotes { footnotes, span } => TurboTree::Footnotes {
+            footnotes: footnotes
+                .into_iter()
+                .map(|mut footnote| {
+                    footnote.text = folder.fold_text(footnote.text);
+                    footnote
+                })
+                .collect(),
+            span,
+        },
+        TurboTree::Toc { entries, span } => TurboTree::Toc {
+            entries: fold_toc(folder, entries),
+            span,
+        },
+        TurboTree::Figure {
+            kind,
+            id,
+            number,
+            caption,
+            content,
+            span,
+        } => TurboTree::Figure {
+            kind,
+            id,
+            number,
+            content: fold_content(folder, content),
+            caption: caption.map(|text| folder.fold_text(text)),
+            span,
+        },
+        node @ (TurboTree::Code { .. }
+        | TurboTree::Horizontal { .. }
+        | TurboTree::Empty { .. }
+        | TurboTree::Include { .. }) => node,
+    }
+}
+
+fn fold_toc<F: Fold + ?Sized>(folder: &mut F, entries: Vec<TocEntry>) -> Vec<TocEntry> {
+    entries
+        .into_iter()
+        .map(|entry| TocEntry {
+            text: folder.fold_text(entry.text),
+            children: fold_toc(folder, entry.children),
+            ..entry
+        })
+        .collect()
+}
+
+/// Folds the children of containers and modifiers.
+pub fn fold_text_children<F: Fold + ?Sized>(folder: &mut F, text: TurboText) -> TurboText {
+    match text {
+        TurboText::TextContainer { text, span } => TurboText::TextContainer {
+            text: text
+                .into_iter()
+                .map(|node| folder.fold_text(node))
+                .collect(),
+            span,
+        },
+        TurboText::Mod { kind, text, span } => TurboText::Mod {
+            kind,
+            text: text
+                .into_iter()
+                .map(|node| folder.fold_text(node))
+                .collect(),
+            span,
+        },
+        text => text,
+    }
+}
+
+#[cfg(test)]
+mod visit_tests {
+    use super::*;
+    use crate::parse_string;
+
+    fn generate(input: &str) -> TurboTree {
+        TurboTree::generate(parse_string(input).unwrap().0).unwrap()
+    }
+
+    #[test]
+    fn visits_labels_and_nested_text() {
+        struct Plain(Vec<String>);
+        impl Visitor for Plain {
+            fn visit_text(&mut self, text: &TurboText) {
+                if let TurboText::Plain { text, .. } = text {
+                    self.0.push(text.clone());
+                }
+                walk_text(self, text)
+            }
+        }
+        let mut plain = Plain(vec![]);
+        plain.visit_tree(&generate("- a\n  *b _c_*\n> d\n> -- e\n"));
+        assert_eq!(plain.0, ["a", "b ", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn folds() {
+        /// Drops checked items and makes headings one level smaller.
+        struct Shift;
+        impl Fold for Shift {
+            fn fold_tree(&mut self, node: TurboTree) -> Vec<TurboTree> {
+                match fold_tree_children(self, node) {
+                    TurboTree::ListItem {
+                        check: Some(true), ..
+                    } => vec![],
+                    TurboTree::Heading {
+                        size,
+                        text,
+                        id,
+                        numbered,
+                        number,
+                        span,
+                    } => vec![TurboTree::Heading {
+                        size: size + 1,
+                        text,
+                        id,
+                        numbered,
+                        number,
+                        span,
+                    }],
+                    node => vec![node],
+                }
+            }
+        }
+        impl VisitorMut for Shift {
+            fn visit_text_mut(&mut self, text: &mut TurboText) {
+                if let TurboText::Link { address, .. } = text {
+                    address.insert_str(0, "/docs/");
+                }
+                walk_text_mut(self, text)
+            }
+        }
+        let mut tree =
+            fold_tree_children(&mut Shift, generate("# a\n- [x] done\n- [ ] todo [x](y)\n"));
+        Shift.visit_tree_mut(&mut tree);
+        assert_eq!(
+            tree.generate_html(None).unwrap(),
+            "<h2 id=\"a\">a</h2>\n<ul>\n<li>\n<input type=\"checkbox\" id=\"checkbox1\"/>\
+             <label for=\"checkbox1\">todo <a href=\"/docs/y\">x</a></label>\n\
+             </li>\n</ul>\n"
+        );
+    }
+}